@@ -1,3 +1,21 @@
+// the two kinds of reset the SH2 knows about. They differ in the vector
+// table entries the cpu starts from, and in which on-chip modules keep their
+// state.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reset {
+    PowerOn,
+    Manual,
+}
+
+
+// an access the bus couldn't complete, as there's nothing mapped there
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BusFault {
+    pub addr: u32,
+    // access size in bytes
+    pub size: u32,
+    pub write: bool,
+}
 
 pub trait Bus {
     // on the SH2, a word is 32 bits wide
@@ -7,4 +25,66 @@ pub trait Bus {
     fn write_byte(&mut self, addr: u32, val: u8);
     fn write_word(&mut self, addr: u32, val: u16);
     fn write_long(&mut self, addr: u32, val: u32);
+
+    // Polled by the cpu before each instr. Returns true once for every NMI
+    // edge the bus has seen since the last call.
+    fn take_nmi(&mut self) -> bool {
+        false
+    }
+
+    // Polled by the cpu before each instr. A reset raised by the bus itself,
+    // for ex. by a watchdog timer, that the cpu should go through now.
+    fn take_reset(&mut self) -> Option<Reset> {
+        None
+    }
+
+    // Polled by the cpu before each instr. Returns true once for every
+    // address error the bus raised itself, for ex. on a misaligned DMA
+    // transfer, for the cpu to take an address error exception for.
+    fn take_address_error(&mut self) -> bool {
+        false
+    }
+
+    // Called by the cpu when it's being reset, before the vector table is
+    // read, so peripherals can go back to their initial state.
+    fn reset(&mut self, _kind: Reset) {}
+
+    // Checked by the cpu after each instr. A bus that can't panic or report
+    // an error from inside an access records it, to be handed out here.
+    fn take_fault(&mut self) -> Option<BusFault> {
+        None
+    }
+
+    // Called by the cpu after each instr with the number of cycles it took,
+    // to clock the peripherals.
+    fn tick(&mut self, _cycles: u64) {}
+
+    // The highest priority interrupt request the bus has for the cpu, as
+    // (level, vector). Polled before each instr, the request stays until
+    // its source is cleared.
+    fn pending_irq(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    // Called by the cpu for each of its own accesses, instr fetches
+    // included, after the access itself. Returns the cycles it took on top
+    // of the one the cpu counts for it, for ex. for wait states. Accesses
+    // made by others, like a debugger, don't go through here.
+    fn access_cycles(&mut self, _addr: u32, _size: u32) -> u64 {
+        0
+    }
+
+    // Cycles the bus kept the cpu from running since the last call, for ex.
+    // for DMA transfers. The cpu adds them to its own count.
+    fn take_stall(&mut self) -> u64 {
+        0
+    }
+
+    // The device on the other end of a single address DMA transfer, selected
+    // by the DACK signal of the channel rather than by an address.
+    fn dack_read(&mut self, _channel: usize, _size: u32) -> u32 {
+        0
+    }
+
+    fn dack_write(&mut self, _channel: usize, _size: u32, _val: u32) {}
 }
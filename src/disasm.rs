@@ -10,10 +10,33 @@ macro_rules! print_dis {
         (if $this.print { print!(concat!($fmt, "\n"), $($arg)*)});
 }
 
+// OP
+macro_rules! no_operand {
+    ($fun:ident, $name:expr) => {
+        fn $fun(&mut self) {
+            print_dis!(self, $name);
+        }
+    }
+}
+
+// OP label, for PC relative branches
 macro_rules! label {
     ($fun:ident, $name:expr) => {
-        fn $fun<B: Bus>(&mut self, _bus:&mut B, disp: i32) {
-            print_dis!(self, "{} label (disp: {:#x})", $name, disp);
+        fn $fun(&mut self, disp: i32) {
+            let addr = self.caret.wrapping_add(4)
+                                 .wrapping_add((disp << 1) as u32);
+            let label = self.add_label(addr);
+            print_dis!(self, "{} {}   (addr: {:#010x}, disp: {:#x})",
+                       $name, label, addr, disp);
+        }
+    }
+}
+
+// OP rn
+macro_rules! n {
+    ($fun:ident, $name:expr) => {
+        fn $fun(&mut self, rn: usize) {
+            print_dis!(self, "{} r{}", $name, rn);
         }
     }
 }
@@ -54,11 +77,87 @@ macro_rules! mn_post_dec {
     }
 }
 
-// op @(disp, PC), rn
-macro_rules! disp_n {
+// OP @rm+, rn
+macro_rules! at_mn_post_inc {
     ($fun:ident, $name:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, rm: usize, rn: usize) {
+            print_dis!(self, "{} @r{}+, r{}", $name, rm, rn);
+        }
+    }
+}
+
+// OP rm, @(r0, rn)
+macro_rules! m_at_r0_n {
+    ($fun:ident, $name:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, rm: usize, rn: usize) {
+            print_dis!(self, "{} r{}, @(r0, r{})", $name, rm, rn);
+        }
+    }
+}
+
+// OP @(r0, rm), rn
+macro_rules! at_r0_m_n {
+    ($fun:ident, $name:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, rm: usize, rn: usize) {
+            print_dis!(self, "{} @(r0, r{}), r{}", $name, rm, rn);
+        }
+    }
+}
+
+// displacements below are printed in bytes, so scaled by the access size
+
+// OP rm, @(disp, rn)
+macro_rules! m_at_disp_n {
+    ($fun:ident, $name:expr, $scale:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, rm: usize, rn: usize,
+                        d: u32) {
+            print_dis!(self, "{} r{}, @({:#x}, r{})", $name, rm, d * $scale, rn);
+        }
+    }
+}
+
+// OP @(disp, rm), rn
+macro_rules! at_disp_m_n {
+    ($fun:ident, $name:expr, $scale:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, rm: usize, rn: usize,
+                        d: u32) {
+            print_dis!(self, "{} @({:#x}, r{}), r{}", $name, d * $scale, rm, rn);
+        }
+    }
+}
+
+// OP r0, @(disp, rn)
+macro_rules! r0_at_disp_n {
+    ($fun:ident, $name:expr, $scale:expr) => {
         fn $fun<B: Bus>(&mut self, _bus:&mut B, d: u32, rn: usize) {
-            print_dis!(self, "{} @({:#x}, PC), r{}", $name, d, rn);
+            print_dis!(self, "{} r0, @({:#x}, r{})", $name, d * $scale, rn);
+        }
+    }
+}
+
+// OP @(disp, rm), r0
+macro_rules! at_disp_m_r0 {
+    ($fun:ident, $name:expr, $scale:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, d: u32, rm: usize) {
+            print_dis!(self, "{} @({:#x}, r{}), r0", $name, d * $scale, rm);
+        }
+    }
+}
+
+// OP r0, @(disp, gbr)
+macro_rules! r0_at_disp_gbr {
+    ($fun:ident, $name:expr, $scale:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, d: u32) {
+            print_dis!(self, "{} r0, @({:#x}, gbr)", $name, d * $scale);
+        }
+    }
+}
+
+// OP @(disp, gbr), r0
+macro_rules! at_disp_gbr_r0 {
+    ($fun:ident, $name:expr, $scale:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, d: u32) {
+            print_dis!(self, "{} @({:#x}, gbr), r0", $name, d * $scale);
         }
     }
 }
@@ -72,6 +171,77 @@ macro_rules! imm_n {
     }
 }
 
+// OP @rm+, @rn+
+macro_rules! at_mn_both_post_inc {
+    ($fun:ident, $name:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, rm: usize, rn: usize) {
+            print_dis!(self, "{} @r{}+, @r{}+", $name, rm, rn);
+        }
+    }
+}
+
+// OP X, rn
+macro_rules! x_n {
+    ($fun:ident, $name:expr, $src_reg:expr) => {
+        fn $fun(&mut self, rn: usize) {
+            print_dis!(self, "{} {}, r{}", $name, $src_reg, rn);
+        }
+    }
+}
+
+// OP rm, X
+macro_rules! m_x {
+    ($fun:ident, $name:expr, $dst_reg:expr) => {
+        fn $fun(&mut self, rm: usize) {
+            print_dis!(self, "{} r{}, {}", $name, rm, $dst_reg);
+        }
+    }
+}
+
+// OP @rm+, X
+macro_rules! m_post_inc_x {
+    ($fun:ident, $name:expr, $dst_reg:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, rm: usize) {
+            print_dis!(self, "{} @r{}+, {}", $name, rm, $dst_reg);
+        }
+    }
+}
+
+// OP #imm, r0
+macro_rules! imm_r0 {
+    ($fun:ident, $name:expr) => {
+        fn $fun(&mut self, i: u32) {
+            print_dis!(self, "{} #{:#x}, r0", $name, i);
+        }
+    }
+}
+
+// OP #imm, @(r0, gbr)
+macro_rules! imm_at_r0_gbr {
+    ($fun:ident, $name:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, i: u32) {
+            print_dis!(self, "{} #{:#x}, @(r0, gbr)", $name, i);
+        }
+    }
+}
+
+// OP @rn
+macro_rules! at_n {
+    ($fun:ident, $name:expr) => {
+        fn $fun<B: Bus>(&mut self, _bus:&mut B, rn: usize) {
+            print_dis!(self, "{} @r{}", $name, rn);
+        }
+    }
+}
+
+macro_rules! at_n_nobus {
+    ($fun:ident, $name:expr) => {
+        fn $fun(&mut self, rn: usize) {
+            print_dis!(self, "{} @r{}", $name, rn);
+        }
+    }
+}
+
 // OP X, @-rn
 macro_rules! n_post_dec {
     ($fun:ident, $name:expr, $src_reg:expr) => {
@@ -90,6 +260,12 @@ pub struct Disassemble {
 }
 
 
+impl Default for Disassemble {
+    fn default() -> Disassemble {
+        Disassemble::new()
+    }
+}
+
 impl Disassemble {
     pub fn new() -> Disassemble {
         Disassemble { pc: 0,
@@ -141,10 +317,7 @@ impl Disassemble {
 
     fn add_label(&mut self, addr: u32) -> String {
         let label_name = format!("l-{}", self.labels.len());
-        if !self.labels.contains_key(&addr) {
-            self.labels.insert(addr, label_name);
-        }
-        String::clone(self.labels.get(&addr).unwrap())
+        String::clone(self.labels.entry(addr).or_insert(label_name))
     }
 
     fn op_most_significant_nibble_unknown<B: Bus>(&mut self, op: u16,
@@ -167,26 +340,128 @@ impl Disassemble {
     }
 
     // ops
+    // 0000
+    m_at_r0_n!(mov_bs0, "mov.b");
+    m_at_r0_n!(mov_ws0, "mov.w");
+    m_at_r0_n!(mov_ls0, "mov.l");
+    mn!(mul_l, "mul.l");
+    x_n!(stc_sr, "stc", "sr");
+    n!(bsrf, "bsrf");
+    no_operand!(clrt, "clrt");
+    no_operand!(nop, "nop");
+    x_n!(sts_mach, "sts", "mach");
+    no_operand!(rts, "rts");
+    x_n!(stc_gbr, "stc", "gbr");
+    no_operand!(sett, "sett");
+    no_operand!(div0u, "div0u");
+    x_n!(sts_macl, "sts", "macl");
+    no_operand!(sleep, "sleep");
+    x_n!(stc_vbr, "stc", "vbr");
+    n!(braf, "braf");
+    no_operand!(clrmac, "clrmac");
+    n!(movt, "movt");
+    x_n!(sts_pr, "sts", "pr");
+    fn rte<B: Bus>(&mut self, _bus: &mut B) {
+        print_dis!(self, "rte");
+    }
+    at_r0_m_n!(mov_bl0, "mov.b");
+    at_r0_m_n!(mov_wl0, "mov.w");
+    at_r0_m_n!(mov_ll0, "mov.l");
+    at_mn_both_post_inc!(mac_l, "mac.l");
+
+    // 0001
+    m_at_disp_n!(mov_ls4, "mov.l", 4);
+
     // 0010
     m_at_n!(mov_bs, "mov.b");
     m_at_n!(mov_ws, "mov.w");
     m_at_n!(mov_ls, "mov.l");
+    mn_post_dec!(mov_bm, "mov.b");
+    mn_post_dec!(mov_wm, "mov.w");
     mn_post_dec!(mov_lm, "mov.l");
+    mn!(div0s, "div0s");
     mn!(tst, "tst");
     mn!(and, "and");
     mn!(xor, "xor");
     mn!(or, "or");
+    mn!(cmp_str, "cmp/str");
+    mn!(xtrct, "xtrct");
+    mn!(mulu_w, "mulu.w");
+    mn!(muls_w, "muls.w");
 
     // 0011
+    mn!(cmp_eq, "cmp/eq");
     mn!(cmp_hs, "cmp/hs");
+    mn!(cmp_ge, "cmp/ge");
+    mn!(div1, "div1");
+    mn!(dmulu_l, "dmulu.l");
+    mn!(cmp_hi, "cmp/hi");
+    mn!(cmp_gt, "cmp/gt");
+    mn!(sub, "sub");
+    mn!(subc, "subc");
+    mn!(subv, "subv");
+    mn!(add, "add");
+    mn!(dmuls_l, "dmuls.l");
+    mn!(addc, "addc");
+    mn!(addv, "addv");
 
     // 0100
+    n!(shll, "shll");
+    n!(shlr, "shlr");
+    n_post_dec!(sts_mmach, "sts.l", "mach");
+    n_post_dec!(stc_msr, "stc.l", "sr");
+    n!(rotl, "rotl");
+    n!(rotr, "rotr");
+    m_post_inc_x!(lds_mmach, "lds.l", "mach");
+    m_post_inc_x!(ldc_msr, "ldc.l", "sr");
+    n!(shll2, "shll2");
+    n!(shlr2, "shlr2");
+    m_x!(lds_mach, "lds", "mach");
+    at_n_nobus!(jsr, "jsr");
+    m_x!(ldc_sr, "ldc", "sr");
+    n!(dt, "dt");
+    n!(cmp_pz, "cmp/pz");
+    n_post_dec!(sts_mmacl, "sts.l", "macl");
+    n_post_dec!(stc_mgbr, "stc.l", "gbr");
+    n!(cmp_pl, "cmp/pl");
+    m_post_inc_x!(lds_mmacl, "lds.l", "macl");
+    m_post_inc_x!(ldc_mgbr, "ldc.l", "gbr");
+    n!(shll8, "shll8");
+    n!(shlr8, "shlr8");
+    m_x!(lds_macl, "lds", "macl");
+    at_n!(tas_b, "tas.b");
+    m_x!(ldc_gbr, "ldc", "gbr");
+    n!(shal, "shal");
+    n!(shar, "shar");
     n_post_dec!(sts_mpr, "sts.l", "pr");
+    n_post_dec!(stc_mvbr, "stc.l", "vbr");
+    n!(rotcl, "rotcl");
+    n!(rotcr, "rotcr");
+    m_post_inc_x!(lds_mpr, "lds.l", "pr");
+    m_post_inc_x!(ldc_mvbr, "ldc.l", "vbr");
+    n!(shll16, "shll16");
+    n!(shlr16, "shlr16");
+    m_x!(lds_pr, "lds", "pr");
+    at_n_nobus!(jmp, "jmp");
+    m_x!(ldc_vbr, "ldc", "vbr");
+    at_mn_both_post_inc!(mac_w, "mac.w");
+
+    // 0101
+    at_disp_m_n!(mov_ll4, "mov.l", 4);
 
     // 0110
     at_mn!(mov_bl, "mov.b");
     at_mn!(mov_wl, "mov.w");
     at_mn!(mov_ll, "mov.l");
+    mn!(mov, "mov");
+    at_mn_post_inc!(mov_bp, "mov.b");
+    at_mn_post_inc!(mov_wp, "mov.w");
+    at_mn_post_inc!(mov_lp, "mov.l");
+    mn!(not, "not");
+    mn!(swap_b, "swap.b");
+    mn!(swap_w, "swap.w");
+    mn!(negc, "negc");
+    mn!(neg, "neg");
     mn!(ext_ub, "extu.b");
     mn!(ext_uw, "extu.w");
     mn!(ext_sb, "exts.b");
@@ -197,36 +472,71 @@ impl Disassemble {
     imm_n!(add_i, "add");
 
     // 1000
-    fn bf(&mut self, disp: i32) {
-        let addr = (self.caret + 4).wrapping_add((disp << 1) as u32);
-        let label = self.add_label(addr);
-        print_dis!(self, "bf {}   (addr: {:#010x}, disp: {:#x})",
-                   label, addr, disp);
+    r0_at_disp_n!(mov_bs4, "mov.b", 1);
+    r0_at_disp_n!(mov_ws4, "mov.w", 2);
+    at_disp_m_r0!(mov_bl4, "mov.b", 1);
+    at_disp_m_r0!(mov_wl4, "mov.w", 2);
+
+    fn cmp_eq_i(&mut self, imm: u32) {
+        print_dis!(self, "cmp/eq #{:#x}, r0", imm as i8);
     }
 
+    label!(bt, "bt");
+    label!(bf, "bf");
+    label!(bt_s, "bt/s");
+    label!(bf_s, "bf/s");
+
     // 1001
     fn mov_wi<B: Bus>(&mut self, bus: &mut B, disp: u32, rn: usize) {
         // PC = 4 bytes past current instr
-        let pc = self.caret + 4;
-        let src = (disp << 1) + pc;
+        let pc = self.caret.wrapping_add(4);
+        let src = pc.wrapping_add(disp << 1);
         let val = bus.read_word(src) as i16 as i32 as u32;
         print_dis!(self, "mov.w @({:#x}, PC), r{}   (addr: {:#010x}, val: {:#06x})",
                    disp, rn, src, val);
     }
 
     // 1010
-    fn bra(&mut self, disp: i32) {
-        let addr = (self.caret + 4).wrapping_add((disp << 1) as u32);
-        let label = self.add_label(addr);
-        print_dis!(self, "bra {}   (addr: {:#010x}, disp: {:#x})",
-                   label, addr, disp);
+    label!(bra, "bra");
+
+    // 1011
+    label!(bsr, "bsr");
+
+    // 1100
+    r0_at_disp_gbr!(mov_bsg, "mov.b", 1);
+    r0_at_disp_gbr!(mov_wsg, "mov.w", 2);
+    r0_at_disp_gbr!(mov_lsg, "mov.l", 4);
+
+    fn trapa<B: Bus>(&mut self, _bus: &mut B, imm: u32) {
+        print_dis!(self, "trapa #{:#x}", imm);
+    }
+
+    at_disp_gbr_r0!(mov_blg, "mov.b", 1);
+    at_disp_gbr_r0!(mov_wlg, "mov.w", 2);
+    at_disp_gbr_r0!(mov_llg, "mov.l", 4);
+
+    fn mova<B: Bus>(&mut self, _bus: &mut B, disp: u32) {
+        // PC = 4 bytes past current instr, with bottom 2 bits set to 0
+        let pc = self.caret.wrapping_add(4) & 0xfffffffc;
+        let addr = pc.wrapping_add(disp << 2);
+        print_dis!(self, "mova @({:#x}, PC), r0   (addr: {:#010x})",
+                   disp, addr);
     }
 
+    imm_r0!(tst_i, "tst");
+    imm_r0!(and_i, "and");
+    imm_r0!(xor_i, "xor");
+    imm_r0!(or_i, "or");
+    imm_at_r0_gbr!(tst_b, "tst.b");
+    imm_at_r0_gbr!(and_b, "and.b");
+    imm_at_r0_gbr!(xor_b, "xor.b");
+    imm_at_r0_gbr!(or_b, "or.b");
+
     // 1101
     fn mov_li<B: Bus>(&mut self, bus: &mut B, disp: u32, rn: usize) {
         // PC = 4 bytes past current instr, with bottom 2 bits set to 0
-        let pc = (self.caret + 4) & 0xfffffffc;
-        let src = (disp << 2) + pc;
+        let pc = self.caret.wrapping_add(4) & 0xfffffffc;
+        let src = pc.wrapping_add(disp << 2);
         let val = bus.read_long(src);
         print_dis!(self, "mov.l @({:#x}, PC), r{}   (addr: {:#010x}, val: {:#010x})",
                    disp, rn, src, val);
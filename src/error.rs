@@ -0,0 +1,46 @@
+use std::error;
+use std::fmt;
+
+use sh2::Regs;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    // undefined code, or a branch in a delay slot, in strict mode
+    IllegalInstr,
+    // misaligned data access or instr fetch, in strict mode
+    AddressError,
+    // an access the bus has nothing mapped at
+    Unmapped,
+}
+
+// Returned by Sh2::step when emulation can't sensibly go on. It carries
+// enough to report where things went wrong and to disassemble around pc.
+#[derive(Clone, Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub what: String,
+    // the instr that caused the error, and its address. There is no op when
+    // the instr couldn't be fetched.
+    pub op: Option<u16>,
+    pub pc: u32,
+    // address and size in bytes of the offending access, if any
+    pub addr: Option<u32>,
+    pub size: Option<u32>,
+    // cpu state at the time
+    pub regs: Box<Regs>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at pc {:#010x}", self.what, self.pc)?;
+        if let Some(op) = self.op {
+            write!(f, ", op {:#06x}", op)?;
+        }
+        if let (Some(addr), Some(size)) = (self.addr, self.size) {
+            write!(f, ", {} byte access to {:#010x}", size, addr)?;
+        }
+        write!(f, "\n\nCPU state:\n{}", self.regs)
+    }
+}
+
+impl error::Error for Error {}
@@ -1,16 +1,16 @@
 #[macro_use]
-
-
 mod ops; // need to import ops before sh2/disasm, because of macro deps
 
 mod bus;
 mod common;
 mod disasm;
+mod error;
 mod sh2;
 mod sh7604;
 
-pub use bus::Bus;
+pub use bus::{Bus, BusFault, Reset};
 pub use common::MemAccess;
 pub use disasm::Disassemble;
-pub use sh2::Sh2;
-pub use sh7604::Sh7604Mem;
+pub use error::{Error, ErrorKind};
+pub use sh2::{Regs, Sh2};
+pub use sh7604::{FrtPin, Sh7604Mem, UnmappedPolicy};
@@ -1,5 +1,3 @@
-#[macro_use]
-
 // instruction format macros
 
 // PC relative 8 bits of displacement
@@ -26,6 +24,13 @@ macro_rules! n_format {
     }
 }
 
+macro_rules! n_nobus_format {
+    ($this:ident, $op:expr, $fun:ident) => {
+        let rn = (($op & 0x0f00) >> 8) as usize;
+        $this.$fun(rn);
+    }
+}
+
 // 2 registers
 macro_rules! nm_format {
     ($this:ident, $bus:expr, $op:expr, $fun:ident) => {
@@ -45,6 +50,33 @@ macro_rules! nm_nobus_format {
     }
 }
 
+// 2 registers + 4 bits of displacement
+macro_rules! nmd_format {
+    ($this:ident, $bus:expr, $op:expr, $fun:ident) => {
+        let rn = (($op & 0x0f00) >> 8) as usize;
+        let rm = (($op & 0x00f0) >> 4) as usize;
+        let d = ($op & 0xf) as u32;
+        $this.$fun($bus, rm, rn, d);
+    }
+}
+
+// 1 register in bits 4-7 + 4 bits of displacement
+macro_rules! nd4_format {
+    ($this:ident, $bus:expr, $op:expr, $fun:ident) => {
+        let rn = (($op & 0x00f0) >> 4) as usize;
+        let d = ($op & 0xf) as u32;
+        $this.$fun($bus, d, rn);
+    }
+}
+
+// GBR or PC relative 8 bits of unsigned displacement
+macro_rules! d_format {
+    ($this:ident, $bus:expr, $op:expr, $fun:ident) => {
+        let d = ($op & 0xff) as u32;
+        $this.$fun($bus, d);
+    }
+}
+
 // PC relative with displacement
 macro_rules! nd8_format {
     ($this:ident, $bus:expr, $op:expr, $fun:ident) => {
@@ -54,6 +86,14 @@ macro_rules! nd8_format {
     }
 }
 
+// 8 bits of immediate, not sign extended
+macro_rules! i_format {
+    ($this:ident, $op:expr, $fun:ident) => {
+        let i = ($op & 0xff) as u32;
+        $this.$fun(i);
+    }
+}
+
 // register + sign extended immediate
 macro_rules! ni_format {
     ($this:ident, $op:expr, $fun:ident) => {
@@ -68,41 +108,144 @@ macro_rules! do_op {
     ($this:ident, $bus:expr, $op:expr) => {
         match $op >> 12 {
             // we're starting with the most significant nibble
+            0b0000 => {
+                match $op & 0xf {
+                    0b0100 => { nm_format!($this, $bus, $op, mov_bs0); },
+                    0b0101 => { nm_format!($this, $bus, $op, mov_ws0); },
+                    0b0110 => { nm_format!($this, $bus, $op, mov_ls0); },
+                    0b0111 => { nm_nobus_format!($this, $op, mul_l); },
+                    0b1100 => { nm_format!($this, $bus, $op, mov_bl0); },
+                    0b1101 => { nm_format!($this, $bus, $op, mov_wl0); },
+                    0b1110 => { nm_format!($this, $bus, $op, mov_ll0); },
+                    0b1111 => { nm_format!($this, $bus, $op, mac_l); },
+                    _ => {
+                        match $op & 0xff {
+                            0b00000010 => { n_nobus_format!($this, $op, stc_sr); },
+                            0b00000011 => { n_nobus_format!($this, $op, bsrf); },
+                            0b00001000 => { $this.clrt(); },
+                            0b00001001 => { $this.nop(); },
+                            0b00001010 => { n_nobus_format!($this, $op, sts_mach); },
+                            0b00001011 => { $this.rts(); },
+                            0b00010010 => { n_nobus_format!($this, $op, stc_gbr); },
+                            0b00011000 => { $this.sett(); },
+                            0b00011001 => { $this.div0u(); },
+                            0b00011010 => { n_nobus_format!($this, $op, sts_macl); },
+                            0b00011011 => { $this.sleep(); },
+                            0b00100010 => { n_nobus_format!($this, $op, stc_vbr); },
+                            0b00100011 => { n_nobus_format!($this, $op, braf); },
+                            0b00101000 => { $this.clrmac(); },
+                            0b00101001 => { n_nobus_format!($this, $op, movt); },
+                            0b00101010 => { n_nobus_format!($this, $op, sts_pr); },
+                            0b00101011 => { $this.rte($bus); },
+                            _ => $this.op_least_significant_byte_unknown($op,
+                                                                         $bus)
+                        }
+                    }
+                }
+            },
+            0b0001 => { nmd_format!($this, $bus, $op, mov_ls4); },
             0b0010 => {
                 // least significant nibble
                 match $op & 0xf {
                     0b0000 => { nm_format!($this, $bus, $op, mov_bs); },
                     0b0001 => { nm_format!($this, $bus, $op, mov_ws); },
                     0b0010 => { nm_format!($this, $bus, $op, mov_ls); },
+                    0b0100 => { nm_format!($this, $bus, $op, mov_bm); },
+                    0b0101 => { nm_format!($this, $bus, $op, mov_wm); },
                     0b0110 => { nm_format!($this, $bus, $op, mov_lm); },
+                    0b0111 => { nm_nobus_format!($this, $op, div0s); },
                     0b1000 => { nm_nobus_format!($this, $op, tst); },
                     0b1001 => { nm_nobus_format!($this, $op, and); },
                     0b1010 => { nm_nobus_format!($this, $op, xor); },
                     0b1011 => { nm_nobus_format!($this, $op, or); },
+                    0b1100 => { nm_nobus_format!($this, $op, cmp_str); },
+                    0b1101 => { nm_nobus_format!($this, $op, xtrct); },
+                    0b1110 => { nm_nobus_format!($this, $op, mulu_w); },
+                    0b1111 => { nm_nobus_format!($this, $op, muls_w); },
                     _ => $this.op_least_significant_nibble_unknown($op, $bus)
                 }
             },
             0b0011 => {
                 match $op & 0xf {
+                    0b0000 => { nm_nobus_format!($this, $op, cmp_eq); },
                     0b0010 => { nm_nobus_format!($this, $op, cmp_hs); },
+                    0b0011 => { nm_nobus_format!($this, $op, cmp_ge); },
+                    0b0100 => { nm_nobus_format!($this, $op, div1); },
+                    0b0101 => { nm_nobus_format!($this, $op, dmulu_l); },
+                    0b0110 => { nm_nobus_format!($this, $op, cmp_hi); },
+                    0b0111 => { nm_nobus_format!($this, $op, cmp_gt); },
+                    0b1000 => { nm_nobus_format!($this, $op, sub); },
+                    0b1010 => { nm_nobus_format!($this, $op, subc); },
+                    0b1011 => { nm_nobus_format!($this, $op, subv); },
+                    0b1100 => { nm_nobus_format!($this, $op, add); },
+                    0b1101 => { nm_nobus_format!($this, $op, dmuls_l); },
+                    0b1110 => { nm_nobus_format!($this, $op, addc); },
+                    0b1111 => { nm_nobus_format!($this, $op, addv); },
                     _ => $this.op_least_significant_nibble_unknown($op, $bus)
                 }
             },
             0b0100 => {
                 if ($op & 0xf) == 0xf {
-                    panic!("please implement MAC.W @Rm+,@Rn+")
+                    nm_format!($this, $bus, $op, mac_w);
                 } else {
                     match $op & 0xff {
+                        0b00000000 => { n_nobus_format!($this, $op, shll); },
+                        0b00000001 => { n_nobus_format!($this, $op, shlr); },
+                        0b00000010 => { n_format!($this, $bus, $op, sts_mmach); },
+                        0b00000011 => { n_format!($this, $bus, $op, stc_msr); },
+                        0b00000100 => { n_nobus_format!($this, $op, rotl); },
+                        0b00000101 => { n_nobus_format!($this, $op, rotr); },
+                        0b00000110 => { n_format!($this, $bus, $op, lds_mmach); },
+                        0b00000111 => { n_format!($this, $bus, $op, ldc_msr); },
+                        0b00001000 => { n_nobus_format!($this, $op, shll2); },
+                        0b00001001 => { n_nobus_format!($this, $op, shlr2); },
+                        0b00001010 => { n_nobus_format!($this, $op, lds_mach); },
+                        0b00001011 => { n_nobus_format!($this, $op, jsr); },
+                        0b00001110 => { n_nobus_format!($this, $op, ldc_sr); },
+                        0b00010000 => { n_nobus_format!($this, $op, dt); },
+                        0b00010001 => { n_nobus_format!($this, $op, cmp_pz); },
+                        0b00010010 => { n_format!($this, $bus, $op, sts_mmacl); },
+                        0b00010011 => { n_format!($this, $bus, $op, stc_mgbr); },
+                        0b00010101 => { n_nobus_format!($this, $op, cmp_pl); },
+                        0b00010110 => { n_format!($this, $bus, $op, lds_mmacl); },
+                        0b00010111 => { n_format!($this, $bus, $op, ldc_mgbr); },
+                        0b00011000 => { n_nobus_format!($this, $op, shll8); },
+                        0b00011001 => { n_nobus_format!($this, $op, shlr8); },
+                        0b00011010 => { n_nobus_format!($this, $op, lds_macl); },
+                        0b00011011 => { n_format!($this, $bus, $op, tas_b); },
+                        0b00011110 => { n_nobus_format!($this, $op, ldc_gbr); },
+                        0b00100000 => { n_nobus_format!($this, $op, shal); },
+                        0b00100001 => { n_nobus_format!($this, $op, shar); },
                         0b00100010 => { n_format!($this, $bus, $op, sts_mpr); },
+                        0b00100011 => { n_format!($this, $bus, $op, stc_mvbr); },
+                        0b00100100 => { n_nobus_format!($this, $op, rotcl); },
+                        0b00100101 => { n_nobus_format!($this, $op, rotcr); },
+                        0b00100110 => { n_format!($this, $bus, $op, lds_mpr); },
+                        0b00100111 => { n_format!($this, $bus, $op, ldc_mvbr); },
+                        0b00101000 => { n_nobus_format!($this, $op, shll16); },
+                        0b00101001 => { n_nobus_format!($this, $op, shlr16); },
+                        0b00101010 => { n_nobus_format!($this, $op, lds_pr); },
+                        0b00101011 => { n_nobus_format!($this, $op, jmp); },
+                        0b00101110 => { n_nobus_format!($this, $op, ldc_vbr); },
                         _ => $this.op_least_significant_byte_unknown($op, $bus)
                     }
                 }
             },
+            0b0101 => { nmd_format!($this, $bus, $op, mov_ll4); },
             0b0110 => {
                 match $op & 0xf {
                     0b0000 => { nm_format!($this, $bus, $op, mov_bl); },
                     0b0001 => { nm_format!($this, $bus, $op, mov_wl); },
                     0b0010 => { nm_format!($this, $bus, $op, mov_ll); },
+                    0b0011 => { nm_nobus_format!($this, $op, mov); },
+                    0b0100 => { nm_format!($this, $bus, $op, mov_bp); },
+                    0b0101 => { nm_format!($this, $bus, $op, mov_wp); },
+                    0b0110 => { nm_format!($this, $bus, $op, mov_lp); },
+                    0b0111 => { nm_nobus_format!($this, $op, not); },
+                    0b1000 => { nm_nobus_format!($this, $op, swap_b); },
+                    0b1001 => { nm_nobus_format!($this, $op, swap_w); },
+                    0b1010 => { nm_nobus_format!($this, $op, negc); },
+                    0b1011 => { nm_nobus_format!($this, $op, neg); },
                     0b1100 => { nm_nobus_format!($this, $op, ext_ub); },
                     0b1101 => { nm_nobus_format!($this, $op, ext_uw); },
                     0b1110 => { nm_nobus_format!($this, $op, ext_sb); },
@@ -113,12 +256,42 @@ macro_rules! do_op {
             0b0111 => { ni_format!($this, $op, add_i); },
             0b1000 => {
                 match ($op & 0x0f00) >> 8 {
+                    0b0000 => { nd4_format!($this, $bus, $op, mov_bs4); },
+                    0b0001 => { nd4_format!($this, $bus, $op, mov_ws4); },
+                    0b0100 => { nd4_format!($this, $bus, $op, mov_bl4); },
+                    0b0101 => { nd4_format!($this, $bus, $op, mov_wl4); },
+                    0b1000 => { i_format!($this, $op, cmp_eq_i); },
+                    0b1001 => { d8_format!($this, $op, bt); },
                     0b1011 => { d8_format!($this, $op, bf); },
+                    0b1101 => { d8_format!($this, $op, bt_s); },
+                    0b1111 => { d8_format!($this, $op, bf_s); },
                     _ => $this.op_2nd_nibble_unknown($op, $bus)
                 }
             },
             0b1001 => { nd8_format!($this, $bus, $op, mov_wi); },
             0b1010 => { d12_format!($this, $op, bra); },
+            0b1011 => { d12_format!($this, $op, bsr); },
+            0b1100 => {
+                match ($op & 0x0f00) >> 8 {
+                    0b0000 => { d_format!($this, $bus, $op, mov_bsg); },
+                    0b0001 => { d_format!($this, $bus, $op, mov_wsg); },
+                    0b0010 => { d_format!($this, $bus, $op, mov_lsg); },
+                    0b0011 => { d_format!($this, $bus, $op, trapa); },
+                    0b0100 => { d_format!($this, $bus, $op, mov_blg); },
+                    0b0101 => { d_format!($this, $bus, $op, mov_wlg); },
+                    0b0110 => { d_format!($this, $bus, $op, mov_llg); },
+                    0b0111 => { d_format!($this, $bus, $op, mova); },
+                    0b1000 => { i_format!($this, $op, tst_i); },
+                    0b1001 => { i_format!($this, $op, and_i); },
+                    0b1010 => { i_format!($this, $op, xor_i); },
+                    0b1011 => { i_format!($this, $op, or_i); },
+                    0b1100 => { d_format!($this, $bus, $op, tst_b); },
+                    0b1101 => { d_format!($this, $bus, $op, and_b); },
+                    0b1110 => { d_format!($this, $bus, $op, xor_b); },
+                    0b1111 => { d_format!($this, $bus, $op, or_b); },
+                    _ => $this.op_2nd_nibble_unknown($op, $bus)
+                }
+            },
             0b1101 => { nd8_format!($this, $bus, $op, mov_li); },
             0b1110 => { ni_format!($this, $op, mov_i); },
             _ => $this.op_most_significant_nibble_unknown($op, $bus)
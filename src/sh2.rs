@@ -1,9 +1,9 @@
 use std::fmt;
 
-use bus::Bus;
-use disasm;
+use bus::{Bus, Reset};
+use error::{Error, ErrorKind};
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Regs {
     // registers
     // [2.1] general purpose registers, sp is #15
@@ -74,6 +74,24 @@ impl Regs {
         self.gpr[15] = sp;
         self.sr_i = 0xF;
     }
+
+    // the status register bits packed together. Reserved bits read as 0.
+    pub fn get_sr(&self) -> u32 {
+        (self.sr_m as u32) << 9 |
+        (self.sr_q as u32) << 8 |
+        (self.sr_i & 0xF) << 4 |
+        (self.sr_s as u32) << 1 |
+        self.sr_t as u32
+    }
+
+    // writes to the reserved bits are ignored
+    pub fn set_sr(&mut self, sr: u32) {
+        self.sr_m = sr & 0x200 != 0;
+        self.sr_q = sr & 0x100 != 0;
+        self.sr_i = (sr >> 4) & 0xF;
+        self.sr_s = sr & 0x2 != 0;
+        self.sr_t = sr & 0x1 != 0;
+    }
 }
 
 impl fmt::Display for Regs {
@@ -84,36 +102,172 @@ impl fmt::Display for Regs {
                 write!(f, "  r{:#02}: {:#010x} ", offset, self.gpr[offset])
                     .unwrap();
             };
-            write!(f, "\n").unwrap();
+            writeln!(f).unwrap();
         };
         write!(f, "\n   pc: {:#010x}   vbr: {:#010x}   gbr: {:#010x}    \
                         pr: {:#010x}  mach: {:#010x}  macl: {:#010x} ",
                self.pc, self.vbr, self.gbr, self.pr, self.mach, self.macl)
             .unwrap();
         write!(f, "\n sr_t: {:<10}  sr_s: {:<10}  sr_q: {:<10}  \
-                   sr_m: {:<10}  sr_i: {:#06b}",
-               self.sr_t, self.sr_s, self.sr_q, self.sr_m, self.sr_i)
+                   sr_m: {:<10}  sr_i: {:#06b}      sr: {:#010x}",
+               self.sr_t, self.sr_s, self.sr_q, self.sr_m, self.sr_i,
+               self.get_sr())
    }
 }
 
+// exception vector table entries
+const VEC_ILLEGAL: u32 = 4;
+const VEC_SLOT_ILLEGAL: u32 = 6;
+const VEC_ADDRESS_ERROR: u32 = 9;
+const VEC_NMI: u32 = 11;
+
+// cycles from the start of a multiply instr until its result can be read
+// from MACH/MACL. The instr itself only occupies the pipeline for part of
+// that; accessing the MAC regs earlier stalls until the multiplier is done.
+const MULT_LATENCY_W: u64 = 3;
+const MULT_LATENCY_L: u64 = 4;
+
+// cycles to stack SR and PC and fetch the vector, on top of the one of the
+// instr that raised the exception
+const EXCEPTION_CYCLES: u64 = 7;
+
 // the main cpu logic
 // references to sections of the SH2 programming manual are enclosed
 // in brackets. ex: [2.1]
 pub struct Sh2 {
     cycles: u64,
+    regs: Regs,
+    // a delayed branch was taken and the instr in its delay slot is up next,
+    // or is being executed. delay_pc is the branch destination
+    delay: bool,
+    delay_pc: u32,
+    // halted by SLEEP, until an interrupt or reset comes along
+    sleeping: bool,
+    // a misaligned data access happened in the current instr, the address
+    // error is taken when the instr is done: (address, size)
+    addr_error: Option<(u32, u32)>,
+    // stop at illegal instrs and address errors instead of taking the
+    // exception, handy when bringing up new code
+    strict: bool,
+    // in strict mode, the reason the current instr turned out illegal
+    illegal: Option<String>,
+    // an NMI edge was seen and is waiting to be accepted
+    nmi: bool,
+    // the bus raised an address error, which is waiting to be accepted
+    bus_addr_error: bool,
+    // pending interrupt request: (level, vector)
+    irq: Option<(u32, u32)>,
+    // the previous instr doesn't allow an interrupt to be accepted before
+    // the next one
+    irq_blocked: bool,
+    // register loaded from memory by the previous instr. Using it right away
+    // costs a cycle, as the load is still in its memory access stage.
+    load_reg: Option<usize>,
+    // cycle at which the multiplier has finished its current operation
+    mult_ready: u64,
+}
+
+// the cpu state before an instr, to go back to when it fails
+struct Checkpoint {
     regs: Regs,
     delay: bool,
     delay_pc: u32,
+    sleeping: bool,
+    load_reg: Option<usize>,
+    mult_ready: u64,
 }
 
 impl fmt::Display for Sh2 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}\
-                   \n  cyc: {:#018x}             del: {:<8}  del_pc: {:#010x}",
-               self.regs, self.cycles, self.delay, self.delay_pc)
+                   \n  cyc: {:#018x}             del: {:<8}  del_pc: {:#010x}\
+                   \n  slp: {:<10}",
+               self.regs, self.cycles, self.delay, self.delay_pc,
+               self.sleeping)
+    }
+}
+
+
+// instrs that change the PC, which are illegal in a delay slot
+fn is_branch(op: u16) -> bool {
+    match op >> 12 {
+        // BSRF, RTS, BRAF, RTE
+        0b0000 => matches!(op & 0xff, 0x03 | 0x0b | 0x23 | 0x2b),
+        // JSR, JMP
+        0b0100 => matches!(op & 0xff, 0x0b | 0x2b),
+        // BT, BF, BT/S, BF/S
+        0b1000 => matches!((op >> 8) & 0xf, 0x9 | 0xb | 0xd | 0xf),
+        // BRA, BSR
+        0b1010 | 0b1011 => true,
+        // TRAPA
+        0b1100 => (op >> 8) & 0xf == 0x3,
+        _ => false
     }
 }
 
+// The manual doesn't allow interrupts to be accepted right after these:
+// LDC, LDC.L, STC, STC.L, LDS, LDS.L, STS and STS.L
+fn blocks_irq(op: u16) -> bool {
+    match op >> 12 {
+        // STC and STS
+        0b0000 => matches!(op & 0xff, 0x02 | 0x12 | 0x22 | 0x0a | 0x1a | 0x2a),
+        // STS.L, STC.L, LDS.L, LDC.L, LDS and LDC
+        0b0100 => op & 0xf0 <= 0x20 &&
+                  matches!(op & 0xf, 0x2 | 0x3 | 0x6 | 0x7 | 0xa | 0xe),
+        _ => false
+    }
+}
+
+// The general register a memory load instr writes to, if it is one:
+// MOV.x @Rm,Rn, MOV.x @Rm+,Rn, MOV.x @(R0,Rm),Rn, MOV.x @(disp,Rm),Rn,
+// MOV.x @(disp,GBR),R0 and MOV.x @(disp,PC),Rn
+fn load_dest(op: u16) -> Option<usize> {
+    let n = ((op >> 8) & 0xf) as usize;
+    match op >> 12 {
+        0b0000 if matches!(op & 0xf, 0xc..=0xe) => Some(n),
+        0b0101 | 0b1001 | 0b1101 => Some(n),
+        0b0110 if matches!(op & 0xf, 0x0..=0x2 | 0x4..=0x6) => Some(n),
+        0b1000 if matches!(n, 0x4 | 0x5) => Some(0),
+        0b1100 if matches!(n, 0x4..=0x6) => Some(0),
+        _ => None
+    }
+}
+
+// whether an instr reads general register r
+fn reads_reg(op: u16, r: usize) -> bool {
+    let n = ((op >> 8) & 0xf) as usize;
+    let m = ((op >> 4) & 0xf) as usize;
+    match op >> 12 {
+        0b0000 => match op & 0xf {
+            // MOV.x Rm,@(R0,Rn), MUL.L and MAC.L
+            0x4..=0x7 | 0xf => r == n || r == m || (op & 0xf < 0x7 && r == 0),
+            // BSRF and BRAF
+            0x3 => r == n,
+            // MOV.x @(R0,Rm),Rn
+            0xc..=0xe => r == m || r == 0,
+            _ => false
+        },
+        0b0001..=0b0011 => r == n || r == m,
+        // MAC.W, the rest only has Rn/Rm in the n field
+        0b0100 => r == n || (op & 0xf == 0xf && r == m),
+        0b0101 | 0b0110 => r == m,
+        0b0111 => r == n,
+        0b1000 => match n {
+            0x0 | 0x1 => r == m || r == 0,
+            0x4 | 0x5 => r == m,
+            0x8 => r == 0,
+            _ => false
+        },
+        0b1100 => matches!(n, 0x0..=0x2 | 0x8..=0xf) && r == 0,
+        _ => false
+    }
+}
+
+impl Default for Sh2 {
+    fn default() -> Sh2 {
+        Sh2::new()
+    }
+}
 
 impl Sh2 {
     pub fn new() -> Sh2 {
@@ -122,7 +276,16 @@ impl Sh2 {
             regs: Regs::new(),
             delay: false,
             delay_pc: 0xdeadbeef,
-
+            sleeping: false,
+            addr_error: None,
+            strict: false,
+            illegal: None,
+            nmi: false,
+            bus_addr_error: false,
+            irq: None,
+            irq_blocked: false,
+            load_reg: None,
+            mult_ready: 0,
         }
     }
 
@@ -134,8 +297,77 @@ impl Sh2 {
         self.regs.pc
     }
 
+    // cpu clock cycles spent since creation, including pipeline stalls
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn get_sr(&self) -> u32 {
+        self.regs.get_sr()
+    }
+
+    pub fn set_sr(&mut self, sr: u32) {
+        self.regs.set_sr(sr);
+    }
+
+    // Reset through the bus like the hardware does: the on-chip modules are
+    // reset, and the initial PC and SP are read from vector 0/1 for
+    // a power-on reset and from vector 2/3 for a manual reset.
+    pub fn reset_from_vectors<B: Bus>(&mut self, bus: &mut B, kind: Reset) {
+        bus.reset(kind);
+        let vector = match kind {
+            Reset::PowerOn => 0,
+            Reset::Manual => 2,
+        };
+        // vbr is 0 after reset, so the vector table is at address 0
+        let pc = bus.read_long(vector * 4);
+        let sp = bus.read_long(vector * 4 + 4);
+        self.reset(pc, sp);
+    }
+
+    pub fn power_on_reset<B: Bus>(&mut self, bus: &mut B) {
+        self.reset_from_vectors(bus, Reset::PowerOn);
+    }
+
+    pub fn manual_reset<B: Bus>(&mut self, bus: &mut B) {
+        self.reset_from_vectors(bus, Reset::Manual);
+    }
+
+    // Reset with an explicit entry point and stack, for code that doesn't
+    // come with a vector table. The bus is left alone.
     pub fn reset(&mut self, pc: u32, sp: u32) {
         self.regs.reset(pc, sp);
+        self.delay = false;
+        self.sleeping = false;
+        self.addr_error = None;
+        self.nmi = false;
+        self.bus_addr_error = false;
+        self.irq_blocked = false;
+        self.load_reg = None;
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    // Request an interrupt with a priority level of 1-15. It is accepted
+    // between instrs when the level is higher than the SR interrupt mask,
+    // after which the handler at the given vector number is called. The
+    // request stays asserted until deassert_irq is called, like the IRL pins
+    // it is modelled after.
+    pub fn assert_irq(&mut self, level: u32, vector: u32) {
+        self.irq = Some((level & 0xF, vector & 0xFF));
+    }
+
+    pub fn deassert_irq(&mut self) {
+        self.irq = None;
+    }
+
+    // Signal an NMI edge. The NMI has priority 16, so it's taken regardless
+    // of the SR interrupt mask, which is then set to 15. A bus that knows
+    // about the NMI pin can also report edges through Bus::take_nmi.
+    pub fn nmi(&mut self) {
+        self.nmi = true;
     }
 
     // This is not wholly kosher perhaps, but for the CPS3 we bypass
@@ -146,92 +378,611 @@ impl Sh2 {
         self.regs.vbr = vbr;
     }
 
-    pub fn step<B: Bus>(&mut self, bus: &mut B) {
-        let op = bus.read_word(self.regs.pc);
+    // Run until at least the given number of cycles has passed.
+    pub fn run<B: Bus>(&mut self, bus: &mut B, cycles: u64)
+                       -> Result<(), Error> {
+        let end = self.cycles + cycles;
+        while self.cycles < end {
+            self.step(bus)?;
+        }
+        Ok(())
+    }
 
-        if self.delay {
-            self.regs.pc = self.delay_pc;
+    // Execute one instr, or accept an interrupt and execute the first instr
+    // of its handler. An error puts the registers back to how they were
+    // before the offending instr, so it runs again on the next step. Cycles
+    // and memory writes up to the error stay applied.
+    pub fn step<B: Bus>(&mut self, bus: &mut B) -> Result<(), Error> {
+        let start = self.cycles;
+        let res = self.exec(bus);
+        bus.tick(self.cycles - start);
+        self.cycles += bus.take_stall();
+        res
+    }
+
+    fn exec<B: Bus>(&mut self, bus: &mut B) -> Result<(), Error> {
+        if let Some(kind) = bus.take_reset() {
+            self.reset_from_vectors(bus, kind);
+        }
+        if bus.take_nmi() {
+            self.nmi = true;
+        }
+        if bus.take_address_error() {
+            self.bus_addr_error = true;
+        }
+
+        // No interrupts between a delayed branch and its slot instr.
+        let blocked = self.irq_blocked || self.delay;
+        self.irq_blocked = false;
+        if !blocked {
+            if self.bus_addr_error {
+                self.bus_addr_error = false;
+                let pc = self.regs.pc;
+                if self.strict {
+                    return Err(self.error(ErrorKind::AddressError,
+                                          "address error on the bus", None,
+                                          pc, None));
+                }
+                self.cycles += 1;
+                self.exception(bus, VEC_ADDRESS_ERROR, pc);
+            } else if self.nmi {
+                self.nmi = false;
+                self.accept_irq(bus, 15, VEC_NMI);
+            } else if let Some((level, vector)) = self.irq_request(bus) {
+                if level > self.regs.sr_i {
+                    self.accept_irq(bus, level, vector);
+                }
+            }
+        }
+
+        let saved = self.checkpoint();
+        let pc = self.regs.pc;
+        let slot = self.delay;
+
+        if self.sleeping {
+            self.cycles += 1;
+            return self.check_bus(bus, None, saved);
+        }
+
+        // instr fetch from an odd address
+        if pc & 1 != 0 {
+            if self.strict {
+                return Err(self.error(ErrorKind::AddressError,
+                                      "instr fetch from odd address", None,
+                                      pc, Some((pc, 2))));
+            }
+            self.cycles += 1;
             self.delay = false;
+            self.exception(bus, VEC_ADDRESS_ERROR, pc);
+            return self.check_bus(bus, None, saved);
+        }
+
+        let op = bus.read_word(pc);
+        self.cycles += bus.access_cycles(pc, 2);
+
+        // While executing, pc always points 2 bytes past the current instr,
+        // also in a delay slot. The branch destination only gets loaded
+        // after the slot instr is done.
+        self.regs.pc = self.regs.pc.wrapping_add(2);
+
+        // load-use stall
+        if let Some(r) = self.load_reg {
+            if reads_reg(op, r) {
+                self.cycles += 1;
+            }
+        }
+
+        if slot && is_branch(op) {
+            self.illegal_instr(bus, "branch in delay slot".to_string());
         } else {
-            self.regs.pc += 2;
+            self.do_op(bus, op);
+        }
+
+        if let Some(what) = self.illegal.take() {
+            self.rewind(saved);
+            return Err(self.error(ErrorKind::IllegalInstr, &what, Some(op),
+                                  pc, None));
         }
 
-        self.do_op(bus, op);
         self.cycles += 1;
+        self.irq_blocked = blocks_irq(op);
+        self.load_reg = load_dest(op);
+
+        // the slot instr can have raised an exception, which cancels the
+        // branch
+        if slot && self.delay {
+            self.regs.pc = self.delay_pc;
+            self.delay = false;
+        }
+
+        // Address errors in a delayed branch are accepted after its
+        // slot instr.
+        if !self.delay {
+            if let Some((addr, size)) = self.addr_error.take() {
+                if self.strict {
+                    self.rewind(saved);
+                    return Err(self.error(ErrorKind::AddressError,
+                                          "misaligned access", Some(op), pc,
+                                          Some((addr, size))));
+                }
+                let ret_pc = self.regs.pc;
+                self.exception(bus, VEC_ADDRESS_ERROR, ret_pc);
+            }
+        }
+
+        self.check_bus(bus, Some(op), saved)
     }
 
-    fn print_op_panic_list<B: Bus>(&mut self, bus: &mut B) {
-        let mut dis = disasm::Disassemble::new();
-        let pc = self.regs.pc;
-        dis.disassemble_range(bus, pc-30, pc+40, pc);
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            regs: self.regs.clone(),
+            delay: self.delay,
+            delay_pc: self.delay_pc,
+            sleeping: self.sleeping,
+            load_reg: self.load_reg,
+            mult_ready: self.mult_ready,
+        }
+    }
+
+    // go back to the state before the failed instr, so it can run again
+    fn rewind(&mut self, saved: Checkpoint) {
+        self.regs = saved.regs;
+        self.delay = saved.delay;
+        self.delay_pc = saved.delay_pc;
+        self.sleeping = saved.sleeping;
+        self.load_reg = saved.load_reg;
+        self.mult_ready = saved.mult_ready;
+    }
+
+    // turn an access the bus couldn't handle into an error
+    fn check_bus<B: Bus>(&mut self, bus: &mut B, op: Option<u16>,
+                         saved: Checkpoint) -> Result<(), Error> {
+        match bus.take_fault() {
+            Some(fault) => {
+                let pc = saved.regs.pc;
+                self.rewind(saved);
+                let what = if fault.write { "unmapped write" }
+                           else { "unmapped read" };
+                Err(self.error(ErrorKind::Unmapped, what, op, pc,
+                               Some((fault.addr, fault.size))))
+            },
+            None => Ok(())
+        }
+    }
+
+    fn error(&self, kind: ErrorKind, what: &str, op: Option<u16>, pc: u32,
+             access: Option<(u32, u32)>) -> Error {
+        Error {
+            kind,
+            what: what.to_string(),
+            op,
+            pc,
+            addr: access.map(|(addr, _)| addr),
+            size: access.map(|(_, size)| size),
+            regs: Box::new(self.regs.clone()),
+        }
+    }
+
+    // Data accesses from instrs go through these. Misaligned word and long
+    // accesses don't reach the bus but raise a CPU address error. Reads
+    // then return 0.
+    fn read_byte<B: Bus>(&mut self, bus: &mut B, addr: u32) -> u8 {
+        let val = bus.read_byte(addr);
+        self.cycles += bus.access_cycles(addr, 1);
+        val
+    }
+
+    fn read_word<B: Bus>(&mut self, bus: &mut B, addr: u32) -> u16 {
+        if addr & 1 != 0 {
+            self.addr_error = Some((addr, 2));
+            return 0;
+        }
+        let val = bus.read_word(addr);
+        self.cycles += bus.access_cycles(addr, 2);
+        val
+    }
+
+    fn read_long<B: Bus>(&mut self, bus: &mut B, addr: u32) -> u32 {
+        if addr & 3 != 0 {
+            self.addr_error = Some((addr, 4));
+            return 0;
+        }
+        let val = bus.read_long(addr);
+        self.cycles += bus.access_cycles(addr, 4);
+        val
+    }
+
+    fn write_byte<B: Bus>(&mut self, bus: &mut B, addr: u32, val: u8) {
+        bus.write_byte(addr, val);
+        self.cycles += bus.access_cycles(addr, 1);
+    }
+
+    fn write_word<B: Bus>(&mut self, bus: &mut B, addr: u32, val: u16) {
+        if addr & 1 != 0 {
+            self.addr_error = Some((addr, 2));
+            return;
+        }
+        bus.write_word(addr, val);
+        self.cycles += bus.access_cycles(addr, 2);
+    }
+
+    fn write_long<B: Bus>(&mut self, bus: &mut B, addr: u32, val: u32) {
+        if addr & 3 != 0 {
+            self.addr_error = Some((addr, 4));
+            return;
+        }
+        bus.write_long(addr, val);
+        self.cycles += bus.access_cycles(addr, 4);
+    }
+
+    // The PC as seen by PC relative operands: 4 bytes past the current
+    // instr. In a delay slot the manual has it point to the branch
+    // destination + 2 instead [MOVA, MOV.W/L @(disp,PC)].
+    fn pc_rel(&self) -> u32 {
+        if self.delay {
+            self.delay_pc.wrapping_add(2)
+        } else {
+            self.regs.pc.wrapping_add(2)
+        }
+    }
+
+    // schedule a delayed branch, the target is taken after the next instr
+    fn delay_branch(&mut self, target: u32) {
+        self.delay = true;
+        self.delay_pc = target;
+    }
+
+    // Undefined code raises a general illegal instruction exception, or a
+    // slot illegal instruction exception when found in a delay slot. So do
+    // instrs that change the PC in a delay slot.
+    fn illegal_instr<B: Bus>(&mut self, bus: &mut B, what: String) {
+        if self.strict {
+            self.illegal = Some(what);
+            return;
+        }
+
+        if self.delay {
+            // the stacked PC is the destination of the delayed branch
+            let ret_pc = self.delay_pc;
+            self.delay = false;
+            self.exception(bus, VEC_SLOT_ILLEGAL, ret_pc);
+        } else {
+            // the stacked PC is the address of the illegal instr itself
+            let ret_pc = self.regs.pc.wrapping_sub(2);
+            self.exception(bus, VEC_ILLEGAL, ret_pc);
+        }
+    }
+
+    // The highest priority request of the interrupt pins and the bus. The
+    // pins win at the same level.
+    fn irq_request<B: Bus>(&self, bus: &B) -> Option<(u32, u32)> {
+        match (self.irq, bus.pending_irq()) {
+            (Some(pin), Some(onchip)) if onchip.0 > pin.0 => Some(onchip),
+            (pin, onchip) => pin.or(onchip),
+        }
+    }
+
+    // stack SR and PC, raise the interrupt mask to the level of the accepted
+    // interrupt and jump to its handler
+    fn accept_irq<B: Bus>(&mut self, bus: &mut B, level: u32, vector: u32) {
+        let ret_pc = self.regs.pc;
+        self.cycles += 1;
+        self.exception(bus, vector, ret_pc);
+        self.regs.sr_i = level;
     }
+
     fn op_most_significant_nibble_unknown<B: Bus>(&mut self, op: u16,
                                                   bus: &mut B) {
-        self.print_op_panic_list(bus);
-        panic!("\n\ndid not recognize most significant nibble \
-                {:#06b} of op {:#06x}\n\nCPU state:\n{}\n\n",
-               op >> 12, op, self)
+        let what = format!("did not recognize most significant nibble {:#06b}",
+                           op >> 12);
+        self.illegal_instr(bus, what);
     }
 
 
     fn op_2nd_nibble_unknown<B: Bus>(&mut self, op: u16, bus: &mut B) {
-        self.print_op_panic_list(bus);
-        panic!("\n\ndid not recognize 2nd nibble \
-                {:#010b} of op {:#06x} \n\nCPU state:\n{}\n\n",
-               op & 0x0F00 >> 8, op, self)
+        let what = format!("did not recognize 2nd nibble {:#06b}",
+                           (op & 0x0F00) >> 8);
+        self.illegal_instr(bus, what);
     }
 
     fn op_least_significant_nibble_unknown<B: Bus>(&mut self, op: u16,
                                                    bus: &mut B) {
-        self.print_op_panic_list(bus);
-        panic!("\n\ndid not recognize least significant nibble \
-                {:#06b} of op {:#06x} \n\nCPU state:\n{}\n\n",
-               op & 0xF, op, self)
+        let what = format!("did not recognize least significant nibble \
+                            {:#06b}", op & 0xF);
+        self.illegal_instr(bus, what);
     }
 
     fn op_least_significant_byte_unknown<B: Bus>(&mut self, op: u16,
                                                  bus: &mut B) {
-        self.print_op_panic_list(bus);
-        panic!("\n\ndid not recognize least significant byte \
-                {:#010b} of op {:#06x} \n\nCPU state:\n{}\n\n",
-               op & 0xFF, op, self)
+        let what = format!("did not recognize least significant byte \
+                            {:#010b}", op & 0xFF);
+        self.illegal_instr(bus, what);
     }
 
     fn do_op<B: Bus>(&mut self, bus: &mut B, op: u16) {
         do_op!(self, bus, op);
     }
 
+    // MACH:MACL as one 64 bit value
+    // Accessing MACH/MACL waits for a running multiply to finish. So does
+    // a new multiply, as there is only one multiplier.
+    fn mult_wait(&mut self) {
+        self.cycles = self.cycles.max(self.mult_ready);
+    }
+
+    fn mult_start(&mut self, latency: u64) {
+        self.mult_wait();
+        self.mult_ready = self.cycles + latency;
+    }
+
+    fn mac(&self) -> u64 {
+        (self.regs.mach as u64) << 32 | self.regs.macl as u64
+    }
+
+    fn set_mac(&mut self, val: u64) {
+        self.regs.mach = (val >> 32) as u32;
+        self.regs.macl = val as u32;
+    }
+
+    // Push SR and the return address on the stack, and continue at the
+    // address found in the vector table entry.
+    fn exception<B: Bus>(&mut self, bus: &mut B, vector: u32, ret_pc: u32) {
+        self.sleeping = false;
+        self.cycles += EXCEPTION_CYCLES;
+        let sr = self.regs.get_sr();
+        let sp = self.regs.gpr[15].wrapping_sub(8);
+        let vector_addr = self.regs.vbr.wrapping_add(vector << 2);
+        self.regs.gpr[15] = sp;
+        bus.write_long(sp.wrapping_add(4), sr);
+        bus.write_long(sp, ret_pc);
+        self.regs.pc = bus.read_long(vector_addr);
+        self.cycles += bus.access_cycles(sp.wrapping_add(4), 4) +
+                       bus.access_cycles(sp, 4) +
+                       bus.access_cycles(vector_addr, 4);
+    }
+
     // instruction handlers
     // doc in format:
     // instr        format            desc                            cyc  t-bit
 
+    // 0000
+    // STC SR,Rn  0000nnnn00000010  SR → Rn                           1    -
+    fn stc_sr(&mut self, rn: usize) {
+        self.regs.gpr[rn] = self.regs.get_sr();
+    }
+
+    // STC GBR,Rn  0000nnnn00010010  GBR → Rn                         1    -
+    fn stc_gbr(&mut self, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gbr;
+    }
+
+    // STC VBR,Rn  0000nnnn00100010  VBR → Rn                         1    -
+    fn stc_vbr(&mut self, rn: usize) {
+        self.regs.gpr[rn] = self.regs.vbr;
+    }
+
+    // MOV.B Rm,@(R0,Rn)  0000nnnnmmmm0100  Rm → (R0 + Rn)            1    -
+    fn mov_bs0<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        let addr = self.regs.gpr[rn].wrapping_add(self.regs.gpr[0]);
+        self.write_byte(bus, addr, self.regs.gpr[rm] as u8);
+    }
+
+    // MOV.W Rm,@(R0,Rn)  0000nnnnmmmm0101  Rm → (R0 + Rn)            1    -
+    fn mov_ws0<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        let addr = self.regs.gpr[rn].wrapping_add(self.regs.gpr[0]);
+        self.write_word(bus, addr, self.regs.gpr[rm] as u16);
+    }
+
+    // MOV.L Rm,@(R0,Rn)  0000nnnnmmmm0110  Rm → (R0 + Rn)            1    -
+    fn mov_ls0<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        let addr = self.regs.gpr[rn].wrapping_add(self.regs.gpr[0]);
+        self.write_long(bus, addr, self.regs.gpr[rm]);
+    }
+
+    // MUL.L Rm,Rn  0000nnnnmmmm0111  Rn × Rm → MACL                 2    -
+    //                                                            (to 4)
+    fn mul_l(&mut self, rm: usize, rn: usize) {
+        self.mult_start(MULT_LATENCY_L);
+        self.regs.macl = self.regs.gpr[rn].wrapping_mul(self.regs.gpr[rm]);
+        self.cycles += 1;
+    }
+
+    // CLRT  0000000000001000  0 → T                                  1    0
+    fn clrt(&mut self) {
+        self.regs.sr_t = false;
+    }
+
+    // SETT  0000000000011000  1 → T                                  1    1
+    fn sett(&mut self) {
+        self.regs.sr_t = true;
+    }
+
+    // NOP  0000000000001001  No operation                            1    -
+    fn nop(&mut self) {
+    }
+
+    // SLEEP  0000000000011011  Sleep                                 3    -
+    fn sleep(&mut self) {
+        self.sleeping = true;
+        self.cycles += 2;
+    }
+
+    // STS MACH,Rn  0000nnnn00001010  MACH → Rn                       1    -
+    fn sts_mach(&mut self, rn: usize) {
+        self.mult_wait();
+        self.regs.gpr[rn] = self.regs.mach;
+    }
+
+    // STS MACL,Rn  0000nnnn00011010  MACL → Rn                       1    -
+    fn sts_macl(&mut self, rn: usize) {
+        self.mult_wait();
+        self.regs.gpr[rn] = self.regs.macl;
+    }
+
+    // STS PR,Rn  0000nnnn00101010  PR → Rn                           1    -
+    fn sts_pr(&mut self, rn: usize) {
+        self.regs.gpr[rn] = self.regs.pr;
+    }
+
+    // RTE  0000000000101011  Delayed branch, stack area → PC/SR      4    LSB
+    fn rte<B: Bus>(&mut self, bus: &mut B) {
+        let pc = self.read_long(bus, self.regs.gpr[15]);
+        self.regs.gpr[15] = self.regs.gpr[15].wrapping_add(4);
+        let sr = self.read_long(bus, self.regs.gpr[15]);
+        self.regs.gpr[15] = self.regs.gpr[15].wrapping_add(4);
+        self.regs.set_sr(sr);
+        self.delay_branch(pc);
+        self.cycles += 3;
+    }
+
+    // CLRMAC  0000000000101000  0 → MACH, MACL                       1    -
+    fn clrmac(&mut self) {
+        self.mult_wait();
+        self.regs.mach = 0;
+        self.regs.macl = 0;
+    }
+
+    // DIV0U  0000000000011001  0 → M/Q/T                             1    0
+    fn div0u(&mut self) {
+        self.regs.sr_m = false;
+        self.regs.sr_q = false;
+        self.regs.sr_t = false;
+    }
+
+    // BSRF Rm  0000mmmm00000011  Delayed branch, PC → PR,            2    -
+    //                            Rm + PC → PC
+    fn bsrf(&mut self, rm: usize) {
+        let pc = self.regs.pc.wrapping_add(2);
+        self.regs.pr = pc;
+        self.delay_branch(pc.wrapping_add(self.regs.gpr[rm]));
+        self.cycles += 1;
+    }
+
+    // RTS  0000000000001011  Delayed branch, PR → PC                 2    -
+    fn rts(&mut self) {
+        let pr = self.regs.pr;
+        self.delay_branch(pr);
+        self.cycles += 1;
+    }
+
+    // BRAF Rm  0000mmmm00100011  Delayed branch, Rm + PC → PC        2    -
+    fn braf(&mut self, rm: usize) {
+        let target = self.regs.pc.wrapping_add(2)
+                                 .wrapping_add(self.regs.gpr[rm]);
+        self.delay_branch(target);
+        self.cycles += 1;
+    }
+
+    // MOVT Rn  0000nnnn00101001  T → Rn                              1    -
+    fn movt(&mut self, rn: usize) {
+        self.regs.gpr[rn] = self.regs.sr_t as u32;
+    }
+
+    // MOV.B @(R0,Rm),Rn  0000nnnnmmmm1100  (R0 + Rm) → Sign          1    -
+    //                                      extension → Rn
+    fn mov_bl0<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        let addr = self.regs.gpr[rm].wrapping_add(self.regs.gpr[0]);
+        self.regs.gpr[rn] = self.read_byte(bus, addr) as i8 as i32 as u32;
+    }
+
+    // MOV.W @(R0,Rm),Rn  0000nnnnmmmm1101  (R0 + Rm) → Sign          1    -
+    //                                      extension → Rn
+    fn mov_wl0<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        let addr = self.regs.gpr[rm].wrapping_add(self.regs.gpr[0]);
+        self.regs.gpr[rn] = self.read_word(bus, addr) as i16 as i32 as u32;
+    }
+
+    // MOV.L @(R0,Rm),Rn  0000nnnnmmmm1110  (R0 + Rm) → Rn            1    -
+    fn mov_ll0<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        let addr = self.regs.gpr[rm].wrapping_add(self.regs.gpr[0]);
+        self.regs.gpr[rn] = self.read_long(bus, addr);
+    }
+
+    // MAC.L @Rm+,@Rn+  0000nnnnmmmm1111  Signed operation of (Rn) ×  3    -
+    //                                    (Rm) + MAC → MAC           (2 to 4)
+    fn mac_l<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        self.mult_start(MULT_LATENCY_L);
+        let src_n = self.read_long(bus, self.regs.gpr[rn]) as i32 as i64;
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_add(4);
+        let src_m = self.read_long(bus, self.regs.gpr[rm]) as i32 as i64;
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(4);
+
+        let mul = src_n * src_m;
+        if self.regs.sr_s {
+            // saturate at 48 bits
+            let max = 0x00007FFF_FFFFFFFF;
+            let min = -0x00008000_00000000;
+            let res = (self.mac() as i64 as i128 + mul as i128)
+                .max(min).min(max);
+            self.set_mac(res as i64 as u64);
+        } else {
+            let res = (self.mac() as i64).wrapping_add(mul);
+            self.set_mac(res as u64);
+        }
+        self.cycles += 2;
+    }
+
+
+    // 0001
+    // MOV.L Rm,@(disp:4,Rn)  0001nnnnmmmmdddd  Rm → (disp × 4 + Rn)  1    -
+    fn mov_ls4<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize,
+                       disp: u32) {
+        let addr = self.regs.gpr[rn].wrapping_add(disp << 2);
+        self.write_long(bus, addr, self.regs.gpr[rm]);
+    }
+
+
     // 0010
     // MOV.B Rm, @Rn  0010nnnnmmmm0000  Rm → (Rn)                     1    -
     fn mov_bs<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
-        bus.write_byte(self.regs.gpr[rn], self.regs.gpr[rm] as u8);
+        self.write_byte(bus, self.regs.gpr[rn], self.regs.gpr[rm] as u8);
     }
 
     // MOV.W Rm, @Rn  0010nnnnmmmm0001  Rm → (Rn)                     1    -
     fn mov_ws<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
-        bus.write_word(self.regs.gpr[rn], self.regs.gpr[rm] as u16);
+        self.write_word(bus, self.regs.gpr[rn], self.regs.gpr[rm] as u16);
     }
 
     // MOV.L Rm, @Rn  0010nnnnmmmm0010  Rm → (Rn)                     1    -
     fn mov_ls<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
-        bus.write_long(self.regs.gpr[rn], self.regs.gpr[rm]);
+        self.write_long(bus, self.regs.gpr[rn], self.regs.gpr[rm]);
+    }
+
+    // MOV.B Rm,@–Rn  0010nnnnmmmm0100  Rn–1 → Rn, Rm → (Rn)          1    -
+    fn mov_bm<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        // read rm first, as rm and rn can be the same register
+        let val = self.regs.gpr[rm];
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(1);
+        self.write_byte(bus, self.regs.gpr[rn], val as u8);
+    }
+
+    // MOV.W Rm,@–Rn  0010nnnnmmmm0101  Rn–2 → Rn, Rm → (Rn)          1    -
+    fn mov_wm<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        let val = self.regs.gpr[rm];
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(2);
+        self.write_word(bus, self.regs.gpr[rn], val as u16);
     }
 
     // MOV.L Rm,@–Rn  0010nnnnmmmm0110  Rn–4 → Rn, Rm → (Rn)          1    -
     fn mov_lm<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
-        self.regs.gpr[rn] -= 4;
-        bus.write_long(self.regs.gpr[rn], self.regs.gpr[rm]);
+        let val = self.regs.gpr[rm];
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(4);
+        self.write_long(bus, self.regs.gpr[rn], val);
+    }
+
+    // DIV0S Rm,Rn  0010nnnnmmmm0111  MSB of Rn → Q, MSB of Rm → M, 1    Calc.
+    //                                M^Q → T                             result
+    fn div0s(&mut self, rm: usize, rn: usize) {
+        self.regs.sr_q = self.regs.gpr[rn] & 0x80000000 != 0;
+        self.regs.sr_m = self.regs.gpr[rm] & 0x80000000 != 0;
+        self.regs.sr_t = self.regs.sr_q != self.regs.sr_m;
     }
 
     // TST Rm, Rn  0010nnnnmmmm1000  Rn & Rm → Rn  Rn & Rm; if the    1    test
     //                               result is 0, 1 → T                   result
     fn tst(&mut self, rm: usize, rn: usize) {
         let res = self.regs.gpr[rn] & self.regs.gpr[rm];
-        self.regs.sr_t = if res == 0 { true } else { false };
+        self.regs.sr_t = res == 0;
     }
 
     // AND Rm, Rn  0010nnnnmmmm1001  Rn & Rm → Rn                     1    -
@@ -249,75 +1000,556 @@ impl Sh2 {
         self.regs.gpr[rn] |= self.regs.gpr[rm];
     }
 
+    // CMP/STR Rm,Rn  0010nnnnmmmm1100  If Rn and Rm have an        1    Comp.
+    //                                  equivalent byte, 1 → T            result
+    fn cmp_str(&mut self, rm: usize, rn: usize) {
+        let tmp = self.regs.gpr[rn] ^ self.regs.gpr[rm];
+        self.regs.sr_t = tmp & 0xFF000000 == 0 ||
+                         tmp & 0x00FF0000 == 0 ||
+                         tmp & 0x0000FF00 == 0 ||
+                         tmp & 0x000000FF == 0;
+    }
+
+    // XTRCT Rm,Rn  0010nnnnmmmm1101  Middle 32 bits of Rm and Rn     1    -
+    //                                → Rn
+    fn xtrct(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = (self.regs.gpr[rm] << 16) |
+                            (self.regs.gpr[rn] >> 16);
+    }
+
+
+    // MULU.W Rm,Rn  0010nnnnmmmm1110  Unsigned operation of Rn ×   1    -
+    //                                 Rm → MACL                     (to 3)
+    fn mulu_w(&mut self, rm: usize, rn: usize) {
+        self.mult_start(MULT_LATENCY_W);
+        self.regs.macl = (self.regs.gpr[rn] & 0xFFFF) *
+                         (self.regs.gpr[rm] & 0xFFFF);
+    }
+
+    // MULS.W Rm,Rn  0010nnnnmmmm1111  Signed operation of Rn × Rm   1    -
+    //                                 → MACL                        (to 3)
+    fn muls_w(&mut self, rm: usize, rn: usize) {
+        self.mult_start(MULT_LATENCY_W);
+        let res = (self.regs.gpr[rn] as i16 as i32) *
+                  (self.regs.gpr[rm] as i16 as i32);
+        self.regs.macl = res as u32;
+    }
+
 
     // 0011
+    // CMP/EQ Rm,Rn  0011nnnnmmmm0000  If Rn = Rm, 1 → T              1    Comp.
+    //                                                                    result
+    fn cmp_eq(&mut self, rm: usize, rn: usize) {
+        self.regs.sr_t = self.regs.gpr[rn] == self.regs.gpr[rm];
+    }
+
     // CMP/HS Rm, Rn  0011nnnnmmmm0010  If Rn≥Rm with                 1    Comp.
     //                                  unsigned data, 1 → T              result
     fn cmp_hs(&mut self, rm: usize, rn: usize) {
-        if self.regs.gpr[rn] >= self.regs.gpr[rm] {
-            self.regs.sr_t = true;
-        } else {
-            self.regs.sr_t = false;
-        }
+        self.regs.sr_t = self.regs.gpr[rn] >= self.regs.gpr[rm];
     }
 
+    // CMP/GE Rm,Rn  0011nnnnmmmm0011  If Rn ≥ Rm with signed         1    Comp.
+    //                                 data, 1 → T                        result
+    fn cmp_ge(&mut self, rm: usize, rn: usize) {
+        self.regs.sr_t =
+            self.regs.gpr[rn] as i32 >= self.regs.gpr[rm] as i32;
+    }
 
-    // 0100
-    // STS.L PR,@–Rn  0100nnnn00100010  Rn–4→ Rn, PR → (Rn)           1    -
-    fn sts_mpr<B: Bus>(&mut self, bus: &mut B, rn: usize) {
-        self.regs.gpr[rn] -= 4;
-        bus.write_long(self.regs.gpr[rn],
-                       self.regs.pr);
-        // TODO: no interrupts are allowed between this instr and the next.
-        // Address errors are accepted.
+    // DIV1 Rm,Rn  0011nnnnmmmm0100  1-step division (Rn ÷ Rm)       1    Calc.
+    //                                                                    result
+    fn div1(&mut self, rm: usize, rn: usize) {
+        let old_q = self.regs.sr_q;
+        let divisor = self.regs.gpr[rm];
+        self.regs.sr_q = self.regs.gpr[rn] & 0x80000000 != 0;
+        let tmp = (self.regs.gpr[rn] << 1) | self.regs.sr_t as u32;
+
+        // The manual spells this out as a nested switch on the old Q, M and
+        // the new Q. Boiled down: subtract when old Q and M agree, add
+        // otherwise, and flip Q on carry/borrow, and again when M is set.
+        let (res, carry) = if old_q == self.regs.sr_m {
+            tmp.overflowing_sub(divisor)
+        } else {
+            tmp.overflowing_add(divisor)
+        };
+        self.regs.gpr[rn] = res;
+        self.regs.sr_q ^= carry ^ self.regs.sr_m;
+        self.regs.sr_t = self.regs.sr_q == self.regs.sr_m;
     }
 
+    // DMULU.L Rm,Rn  0011nnnnmmmm0101  Unsigned operation of Rn ×  2    -
+    //                                  Rm → MACH, MACL              (to 4)
+    fn dmulu_l(&mut self, rm: usize, rn: usize) {
+        self.mult_start(MULT_LATENCY_L);
+        let res = self.regs.gpr[rn] as u64 * self.regs.gpr[rm] as u64;
+        self.set_mac(res);
+        self.cycles += 1;
+    }
 
-    // 0110
-    // MOV.B @Rm,Rn  0110nnnnmmmm0000  (Rm) → Sign extension → Rn     1    -
-    fn mov_bl<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
-        self.regs.gpr[rn] =
-            bus.read_byte(self.regs.gpr[rm]) as i8 as i32 as u32;
+    // CMP/HI Rm,Rn  0011nnnnmmmm0110  If Rn > Rm with unsigned       1    Comp.
+    //                                 data, 1 → T                        result
+    fn cmp_hi(&mut self, rm: usize, rn: usize) {
+        self.regs.sr_t = self.regs.gpr[rn] > self.regs.gpr[rm];
     }
 
+    // CMP/GT Rm,Rn  0011nnnnmmmm0111  If Rn > Rm with signed         1    Comp.
+    //                                 data, 1 → T                        result
+    fn cmp_gt(&mut self, rm: usize, rn: usize) {
+        self.regs.sr_t =
+            self.regs.gpr[rn] as i32 > self.regs.gpr[rm] as i32;
+    }
 
-    // MOV.W @Rm,Rn  0110nnnnmmmm0001  (Rm) → Sign extension → Rn     1    -
-    fn mov_wl<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
-        self.regs.gpr[rn] =
-            bus.read_word(self.regs.gpr[rm]) as i16 as i32 as u32;
+    // SUB Rm,Rn  0011nnnnmmmm1000  Rn – Rm → Rn                      1    -
+    fn sub(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(self.regs.gpr[rm]);
     }
 
-    // MOV.L @Rm, Rn  0110nnnnmmmm0010  (Rm) → Rn                     1    -
-    fn mov_ll<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
-        self.regs.gpr[rn] = bus.read_long(self.regs.gpr[rm]);
+    // SUBC Rm,Rn  0011nnnnmmmm1010  Rn – Rm– T → Rn,                 1    Borrow
+    //                               Borrow → T
+    fn subc(&mut self, rm: usize, rn: usize) {
+        let (tmp, borrow0) =
+            self.regs.gpr[rn].overflowing_sub(self.regs.gpr[rm]);
+        let (res, borrow1) = tmp.overflowing_sub(self.regs.sr_t as u32);
+        self.regs.gpr[rn] = res;
+        self.regs.sr_t = borrow0 || borrow1;
     }
 
-    // EXTU.B rm, rn  0110nnnnmmmm1100  A byte in Rm is sign-         1    -
-    //                                  extended → Rn
-    fn ext_ub(&mut self, rm: usize, rn: usize) {
-        self.regs.gpr[rn] = self.regs.gpr[rm] & 0x000000FF
+    // SUBV Rm,Rn  0011nnnnmmmm1011  Rn – Rm → Rn,                    1    Under-
+    //                               Underflow → T                        flow
+    fn subv(&mut self, rm: usize, rn: usize) {
+        let (res, underflow) = (self.regs.gpr[rn] as i32)
+            .overflowing_sub(self.regs.gpr[rm] as i32);
+        self.regs.gpr[rn] = res as u32;
+        self.regs.sr_t = underflow;
     }
 
-    // EXTU.W rm, rn  0110nnnnmmmm1101  A byte in Rm is sign-         1    -
-    //                                  extended → Rn
-    fn ext_uw(&mut self, rm: usize, rn: usize) {
-        self.regs.gpr[rn] = self.regs.gpr[rm] & 0x0000FFFF;
+    // ADD Rm,Rn  0011nnnnmmmm1100  Rn + Rm → Rn                      1    -
+    fn add(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_add(self.regs.gpr[rm]);
     }
 
-    // EXTS.B rm, rn  0110nnnnmmmm1110  A byte in Rm is sign-         1    -
-    //                                  extended → Rn
-    fn ext_sb(&mut self, rm: usize, rn: usize) {
-        self.regs.gpr[rn] = self.regs.gpr[rm] as i8 as i32 as u32;
+    // DMULS.L Rm,Rn  0011nnnnmmmm1101  Signed operation of Rn ×    2    -
+    //                                  Rm → MACH, MACL              (to 4)
+    fn dmuls_l(&mut self, rm: usize, rn: usize) {
+        self.mult_start(MULT_LATENCY_L);
+        let res = self.regs.gpr[rn] as i32 as i64 *
+                  self.regs.gpr[rm] as i32 as i64;
+        self.set_mac(res as u64);
+        self.cycles += 1;
     }
 
-    // EXTS.W rm, rn  0110nnnnmmmm1111  A byte in Rm is sign-         1    -
-    //                                  extended → Rn
-    fn ext_sw(&mut self, rm: usize, rn: usize) {
-        self.regs.gpr[rn] = self.regs.gpr[rm] as i16 as i32 as u32;
+    // ADDC Rm,Rn  0011nnnnmmmm1110  Rn + Rm + T → Rn,                1    Carry
+    //                               Carry → T
+    fn addc(&mut self, rm: usize, rn: usize) {
+        let (tmp, carry0) =
+            self.regs.gpr[rn].overflowing_add(self.regs.gpr[rm]);
+        let (res, carry1) = tmp.overflowing_add(self.regs.sr_t as u32);
+        self.regs.gpr[rn] = res;
+        self.regs.sr_t = carry0 || carry1;
     }
 
+    // ADDV Rm,Rn  0011nnnnmmmm1111  Rn + Rm → Rn,                    1    Over-
+    //                               Overflow → T                         flow
+    fn addv(&mut self, rm: usize, rn: usize) {
+        let (res, overflow) = (self.regs.gpr[rn] as i32)
+            .overflowing_add(self.regs.gpr[rm] as i32);
+        self.regs.gpr[rn] = res as u32;
+        self.regs.sr_t = overflow;
+    }
 
-    // 0111
+
+    // 0100
+    // SHLL Rn  0100nnnn00000000  T ← Rn ← 0                          1    MSB
+    fn shll(&mut self, rn: usize) {
+        self.regs.sr_t = self.regs.gpr[rn] & 0x80000000 != 0;
+        self.regs.gpr[rn] <<= 1;
+    }
+
+    // SHLR Rn  0100nnnn00000001  0 → Rn → T                          1    LSB
+    fn shlr(&mut self, rn: usize) {
+        self.regs.sr_t = self.regs.gpr[rn] & 1 != 0;
+        self.regs.gpr[rn] >>= 1;
+    }
+
+    // STS.L MACH,@–Rn  0100nnnn00000010  Rn–4 → Rn, MACH → (Rn)      1    -
+    fn sts_mmach<B: Bus>(&mut self, bus: &mut B, rn: usize) {
+        self.mult_wait();
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(4);
+        self.write_long(bus, self.regs.gpr[rn], self.regs.mach);
+    }
+
+    // STC.L SR,@–Rn  0100nnnn00000011  Rn – 4 → Rn, SR → (Rn)       2    -
+    fn stc_msr<B: Bus>(&mut self, bus: &mut B, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(4);
+        self.write_long(bus, self.regs.gpr[rn], self.regs.get_sr());
+        self.cycles += 1;
+    }
+
+    // ROTL Rn  0100nnnn00000100  T ← Rn ← MSB                        1    MSB
+    fn rotl(&mut self, rn: usize) {
+        self.regs.sr_t = self.regs.gpr[rn] & 0x80000000 != 0;
+        self.regs.gpr[rn] = self.regs.gpr[rn].rotate_left(1);
+    }
+
+    // ROTR Rn  0100nnnn00000101  LSB → Rn → T                        1    LSB
+    fn rotr(&mut self, rn: usize) {
+        self.regs.sr_t = self.regs.gpr[rn] & 1 != 0;
+        self.regs.gpr[rn] = self.regs.gpr[rn].rotate_right(1);
+    }
+
+    // LDS.L @Rm+,MACH  0100mmmm00000110  (Rm) → MACH, Rm + 4 → Rm    1    -
+    fn lds_mmach<B: Bus>(&mut self, bus: &mut B, rm: usize) {
+        self.mult_wait();
+        self.regs.mach = self.read_long(bus, self.regs.gpr[rm]);
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(4);
+    }
+
+    // JSR @Rm  0100mmmm00001011  Delayed branch, PC → PR, Rm → PC    2    -
+    fn jsr(&mut self, rm: usize) {
+        self.regs.pr = self.regs.pc.wrapping_add(2);
+        let target = self.regs.gpr[rm];
+        self.delay_branch(target);
+        self.cycles += 1;
+    }
+
+    // LDC.L @Rm+,SR  0100mmmm00000111  (Rm) → SR, Rm + 4 → Rm       3    LSB
+    fn ldc_msr<B: Bus>(&mut self, bus: &mut B, rm: usize) {
+        let sr = self.read_long(bus, self.regs.gpr[rm]);
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(4);
+        self.regs.set_sr(sr);
+        self.cycles += 2;
+    }
+
+    // SHLL2 Rn  0100nnnn00001000  Rn << 2 → Rn                       1    -
+    fn shll2(&mut self, rn: usize) {
+        self.regs.gpr[rn] <<= 2;
+    }
+
+    // SHLR2 Rn  0100nnnn00001001  Rn >> 2 → Rn                       1    -
+    fn shlr2(&mut self, rn: usize) {
+        self.regs.gpr[rn] >>= 2;
+    }
+
+    // LDS Rm,MACH  0100mmmm00001010  Rm → MACH                       1    -
+    fn lds_mach(&mut self, rm: usize) {
+        self.mult_wait();
+        self.regs.mach = self.regs.gpr[rm];
+    }
+
+    // LDC Rm,SR  0100mmmm00001110  Rm → SR                           1    LSB
+    fn ldc_sr(&mut self, rm: usize) {
+        let sr = self.regs.gpr[rm];
+        self.regs.set_sr(sr);
+    }
+
+    // DT Rn  0100nnnn00010000  Rn – 1 → Rn, when Rn is 0,            1    Comp.
+    //                          1 → T. When Rn is nonzero, 0 → T          result
+    fn dt(&mut self, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(1);
+        self.regs.sr_t = self.regs.gpr[rn] == 0;
+    }
+
+    // CMP/PZ Rn  0100nnnn00010001  If Rn ≥ 0, 1 → T                  1    Comp.
+    //                                                                    result
+    fn cmp_pz(&mut self, rn: usize) {
+        self.regs.sr_t = self.regs.gpr[rn] as i32 >= 0;
+    }
+
+    // STS.L MACL,@–Rn  0100nnnn00010010  Rn–4 → Rn, MACL → (Rn)      1    -
+    fn sts_mmacl<B: Bus>(&mut self, bus: &mut B, rn: usize) {
+        self.mult_wait();
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(4);
+        self.write_long(bus, self.regs.gpr[rn], self.regs.macl);
+    }
+
+    // STC.L GBR,@–Rn  0100nnnn00010011  Rn – 4 → Rn, GBR → (Rn)     2    -
+    fn stc_mgbr<B: Bus>(&mut self, bus: &mut B, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(4);
+        self.write_long(bus, self.regs.gpr[rn], self.regs.gbr);
+        self.cycles += 1;
+    }
+
+    // CMP/PL Rn  0100nnnn00010101  If Rn > 0, 1 → T                  1    Comp.
+    //                                                                    result
+    fn cmp_pl(&mut self, rn: usize) {
+        self.regs.sr_t = self.regs.gpr[rn] as i32 > 0;
+    }
+
+    // LDS.L @Rm+,MACL  0100mmmm00010110  (Rm) → MACL, Rm + 4 → Rm    1    -
+    fn lds_mmacl<B: Bus>(&mut self, bus: &mut B, rm: usize) {
+        self.mult_wait();
+        self.regs.macl = self.read_long(bus, self.regs.gpr[rm]);
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(4);
+    }
+
+    // LDC.L @Rm+,GBR  0100mmmm00010111  (Rm) → GBR, Rm + 4 → Rm     3    -
+    fn ldc_mgbr<B: Bus>(&mut self, bus: &mut B, rm: usize) {
+        self.regs.gbr = self.read_long(bus, self.regs.gpr[rm]);
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(4);
+        self.cycles += 2;
+    }
+
+    // SHLL8 Rn  0100nnnn00011000  Rn << 8 → Rn                       1    -
+    fn shll8(&mut self, rn: usize) {
+        self.regs.gpr[rn] <<= 8;
+    }
+
+    // SHLR8 Rn  0100nnnn00011001  Rn >> 8 → Rn                       1    -
+    fn shlr8(&mut self, rn: usize) {
+        self.regs.gpr[rn] >>= 8;
+    }
+
+    // LDS Rm,MACL  0100mmmm00011010  Rm → MACL                       1    -
+    fn lds_macl(&mut self, rm: usize) {
+        self.mult_wait();
+        self.regs.macl = self.regs.gpr[rm];
+    }
+
+    // TAS.B @Rn  0100nnnn00011011  If (Rn) is 0, 1 → T; 1 → MSB of   4    Test
+    //                              (Rn)                                  result
+    fn tas_b<B: Bus>(&mut self, bus: &mut B, rn: usize) {
+        // On hardware the bus is locked between the read and the write.
+        // Nothing else gets to run in between here anyway.
+        let addr = self.regs.gpr[rn];
+        let val = self.read_byte(bus, addr);
+        self.regs.sr_t = val == 0;
+        self.write_byte(bus, addr, val | 0x80);
+        self.cycles += 3;
+    }
+
+    // LDC Rm,GBR  0100mmmm00011110  Rm → GBR                         1    -
+    fn ldc_gbr(&mut self, rm: usize) {
+        self.regs.gbr = self.regs.gpr[rm];
+    }
+
+    // SHAL Rn  0100nnnn00100000  T ← Rn ← 0                          1    MSB
+    fn shal(&mut self, rn: usize) {
+        self.regs.sr_t = self.regs.gpr[rn] & 0x80000000 != 0;
+        self.regs.gpr[rn] <<= 1;
+    }
+
+    // SHAR Rn  0100nnnn00100001  MSB → Rn → T                        1    LSB
+    fn shar(&mut self, rn: usize) {
+        self.regs.sr_t = self.regs.gpr[rn] & 1 != 0;
+        self.regs.gpr[rn] = ((self.regs.gpr[rn] as i32) >> 1) as u32;
+    }
+
+    // STS.L PR,@–Rn  0100nnnn00100010  Rn–4→ Rn, PR → (Rn)           1    -
+    fn sts_mpr<B: Bus>(&mut self, bus: &mut B, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(4);
+        self.write_long(bus, self.regs.gpr[rn],
+                       self.regs.pr);
+        // No interrupts are allowed between this instr and the next, see
+        // blocks_irq. Address errors are accepted.
+    }
+
+    // STC.L VBR,@–Rn  0100nnnn00100011  Rn – 4 → Rn, VBR → (Rn)     2    -
+    fn stc_mvbr<B: Bus>(&mut self, bus: &mut B, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_sub(4);
+        self.write_long(bus, self.regs.gpr[rn], self.regs.vbr);
+        self.cycles += 1;
+    }
+
+    // ROTCL Rn  0100nnnn00100100  T ← Rn ← T                         1    MSB
+    fn rotcl(&mut self, rn: usize) {
+        let msb = self.regs.gpr[rn] & 0x80000000 != 0;
+        self.regs.gpr[rn] = (self.regs.gpr[rn] << 1) | self.regs.sr_t as u32;
+        self.regs.sr_t = msb;
+    }
+
+    // ROTCR Rn  0100nnnn00100101  T → Rn → T                         1    LSB
+    fn rotcr(&mut self, rn: usize) {
+        let lsb = self.regs.gpr[rn] & 1 != 0;
+        self.regs.gpr[rn] = (self.regs.gpr[rn] >> 1) |
+                            (self.regs.sr_t as u32) << 31;
+        self.regs.sr_t = lsb;
+    }
+
+    // LDS Rm,PR  0100mmmm00101010  Rm → PR                           1    -
+    fn lds_pr(&mut self, rm: usize) {
+        self.regs.pr = self.regs.gpr[rm];
+    }
+
+    // JMP @Rm  0100mmmm00101011  Delayed branch, Rm → PC             2    -
+    fn jmp(&mut self, rm: usize) {
+        let target = self.regs.gpr[rm];
+        self.delay_branch(target);
+        self.cycles += 1;
+    }
+
+    // LDS.L @Rm+,PR  0100mmmm00100110  (Rm) → PR, Rm + 4 → Rm       1    -
+    fn lds_mpr<B: Bus>(&mut self, bus: &mut B, rm: usize) {
+        self.regs.pr = self.read_long(bus, self.regs.gpr[rm]);
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(4);
+    }
+
+    // LDC.L @Rm+,VBR  0100mmmm00100111  (Rm) → VBR, Rm + 4 → Rm     3    -
+    fn ldc_mvbr<B: Bus>(&mut self, bus: &mut B, rm: usize) {
+        self.regs.vbr = self.read_long(bus, self.regs.gpr[rm]);
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(4);
+        self.cycles += 2;
+    }
+
+    // SHLL16 Rn  0100nnnn00101000  Rn << 16 → Rn                     1    -
+    fn shll16(&mut self, rn: usize) {
+        self.regs.gpr[rn] <<= 16;
+    }
+
+    // SHLR16 Rn  0100nnnn00101001  Rn >> 16 → Rn                     1    -
+    fn shlr16(&mut self, rn: usize) {
+        self.regs.gpr[rn] >>= 16;
+    }
+
+    // LDC Rm,VBR  0100mmmm00101110  Rm → VBR                         1    -
+    fn ldc_vbr(&mut self, rm: usize) {
+        self.regs.vbr = self.regs.gpr[rm];
+    }
+
+    // MAC.W @Rm+,@Rn+  0100nnnnmmmm1111  Signed operation of (Rn) ×  3    -
+    //                                    (Rm) + MAC → MAC             (2)
+    fn mac_w<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        self.mult_start(MULT_LATENCY_W);
+        let src_n = self.read_word(bus, self.regs.gpr[rn]) as i16 as i32;
+        self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_add(2);
+        let src_m = self.read_word(bus, self.regs.gpr[rm]) as i16 as i32;
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(2);
+
+        let mul = src_n * src_m;
+        if self.regs.sr_s {
+            // saturate at 32 bits, only MACL takes part. On overflow the lsb
+            // of MACH gets set.
+            let (res, overflow) = (self.regs.macl as i32).overflowing_add(mul);
+            if overflow {
+                self.regs.macl = if mul < 0 { 0x80000000 } else { 0x7FFFFFFF };
+                self.regs.mach |= 1;
+            } else {
+                self.regs.macl = res as u32;
+            }
+        } else {
+            let res = (self.mac() as i64).wrapping_add(mul as i64);
+            self.set_mac(res as u64);
+        }
+        self.cycles += 2;
+    }
+
+
+    // 0101
+    // MOV.L @(disp:4,Rm),Rn  0101nnnnmmmmdddd  (disp × 4 + Rm) → Rn  1    -
+    fn mov_ll4<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize,
+                       disp: u32) {
+        let addr = self.regs.gpr[rm].wrapping_add(disp << 2);
+        self.regs.gpr[rn] = self.read_long(bus, addr);
+    }
+
+
+    // 0110
+    // MOV.B @Rm,Rn  0110nnnnmmmm0000  (Rm) → Sign extension → Rn     1    -
+    fn mov_bl<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        self.regs.gpr[rn] =
+            self.read_byte(bus, self.regs.gpr[rm]) as i8 as i32 as u32;
+    }
+
+
+    // MOV.W @Rm,Rn  0110nnnnmmmm0001  (Rm) → Sign extension → Rn     1    -
+    fn mov_wl<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        self.regs.gpr[rn] =
+            self.read_word(bus, self.regs.gpr[rm]) as i16 as i32 as u32;
+    }
+
+    // MOV.L @Rm, Rn  0110nnnnmmmm0010  (Rm) → Rn                     1    -
+    fn mov_ll<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = self.read_long(bus, self.regs.gpr[rm]);
+    }
+
+    // MOV Rm,Rn  0110nnnnmmmm0011  Rm → Rn                           1    -
+    fn mov(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rm];
+    }
+
+    // MOV.B @Rm+,Rn  0110nnnnmmmm0100  (Rm) → Sign extension → Rn,   1    -
+    //                                  Rm + 1 → Rm
+    fn mov_bp<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        let val = self.read_byte(bus, self.regs.gpr[rm]) as i8 as i32 as u32;
+        // when rm == rn, the loaded value wins over the increment
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(1);
+        self.regs.gpr[rn] = val;
+    }
+
+    // MOV.W @Rm+,Rn  0110nnnnmmmm0101  (Rm) → Sign extension → Rn,   1    -
+    //                                  Rm + 2 → Rm
+    fn mov_wp<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        let val = self.read_word(bus, self.regs.gpr[rm]) as i16 as i32 as u32;
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(2);
+        self.regs.gpr[rn] = val;
+    }
+
+    // MOV.L @Rm+,Rn  0110nnnnmmmm0110  (Rm) → Rn, Rm + 4 → Rm        1    -
+    fn mov_lp<B: Bus>(&mut self, bus: &mut B, rm: usize, rn: usize) {
+        let val = self.read_long(bus, self.regs.gpr[rm]);
+        self.regs.gpr[rm] = self.regs.gpr[rm].wrapping_add(4);
+        self.regs.gpr[rn] = val;
+    }
+
+    // NOT Rm,Rn  0110nnnnmmmm0111  ~Rm → Rn                          1    -
+    fn not(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = !self.regs.gpr[rm];
+    }
+
+    // SWAP.B Rm,Rn  0110nnnnmmmm1000  Rm → Swap bottom two bytes     1    -
+    //                                 → Rn
+    fn swap_b(&mut self, rm: usize, rn: usize) {
+        let val = self.regs.gpr[rm];
+        self.regs.gpr[rn] = (val & 0xFFFF0000) |
+                            ((val & 0xFF) << 8) |
+                            ((val >> 8) & 0xFF);
+    }
+
+    // SWAP.W Rm,Rn  0110nnnnmmmm1001  Rm → Swap two consecutive      1    -
+    //                                 words → Rn
+    fn swap_w(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rm].rotate_left(16);
+    }
+
+    // NEGC Rm,Rn  0110nnnnmmmm1010  0 – Rm – T → Rn,                 1    Borrow
+    //                               Borrow → T
+    fn negc(&mut self, rm: usize, rn: usize) {
+        let (tmp, borrow0) = 0u32.overflowing_sub(self.regs.gpr[rm]);
+        let (res, borrow1) = tmp.overflowing_sub(self.regs.sr_t as u32);
+        self.regs.gpr[rn] = res;
+        self.regs.sr_t = borrow0 || borrow1;
+    }
+
+    // NEG Rm,Rn  0110nnnnmmmm1011  0 – Rm → Rn                       1    -
+    fn neg(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = 0u32.wrapping_sub(self.regs.gpr[rm]);
+    }
+
+    // EXTU.B rm, rn  0110nnnnmmmm1100  A byte in Rm is sign-         1    -
+    //                                  extended → Rn
+    fn ext_ub(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rm] & 0x000000FF
+    }
+
+    // EXTU.W rm, rn  0110nnnnmmmm1101  A byte in Rm is sign-         1    -
+    //                                  extended → Rn
+    fn ext_uw(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rm] & 0x0000FFFF;
+    }
+
+    // EXTS.B rm, rn  0110nnnnmmmm1110  A byte in Rm is sign-         1    -
+    //                                  extended → Rn
+    fn ext_sb(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rm] as i8 as i32 as u32;
+    }
+
+    // EXTS.W rm, rn  0110nnnnmmmm1111  A byte in Rm is sign-         1    -
+    //                                  extended → Rn
+    fn ext_sw(&mut self, rm: usize, rn: usize) {
+        self.regs.gpr[rn] = self.regs.gpr[rm] as i16 as i32 as u32;
+    }
+
+
+    // 0111
     // ADD #imm, Rn  0111nnnniiiiiiii  Rn + imm → Rn                  1    -
     fn add_i(&mut self, imm: u32, rn: usize) {
         self.regs.gpr[rn] = self.regs.gpr[rn].wrapping_add(imm);
@@ -325,23 +1557,88 @@ impl Sh2 {
 
 
     // 1000
+    // MOV.B R0,@(disp:4,Rn)  10000000nnnndddd  R0 → (disp + Rn)      1    -
+    fn mov_bs4<B: Bus>(&mut self, bus: &mut B, disp: u32, rn: usize) {
+        let addr = self.regs.gpr[rn].wrapping_add(disp);
+        self.write_byte(bus, addr, self.regs.gpr[0] as u8);
+    }
+
+    // MOV.W R0,@(disp:4,Rn)  10000001nnnndddd  R0 → (disp × 2 + Rn)  1    -
+    fn mov_ws4<B: Bus>(&mut self, bus: &mut B, disp: u32, rn: usize) {
+        let addr = self.regs.gpr[rn].wrapping_add(disp << 1);
+        self.write_word(bus, addr, self.regs.gpr[0] as u16);
+    }
+
+    // MOV.B @(disp:4,Rm),R0  10000100mmmmdddd  (disp + Rm) → Sign    1    -
+    //                                          extension → R0
+    fn mov_bl4<B: Bus>(&mut self, bus: &mut B, disp: u32, rm: usize) {
+        let addr = self.regs.gpr[rm].wrapping_add(disp);
+        self.regs.gpr[0] = self.read_byte(bus, addr) as i8 as i32 as u32;
+    }
+
+    // MOV.W @(disp:4,Rm),R0  10000101mmmmdddd  (disp × 2 + Rm) →     1    -
+    //                                          Sign extension → R0
+    fn mov_wl4<B: Bus>(&mut self, bus: &mut B, disp: u32, rm: usize) {
+        let addr = self.regs.gpr[rm].wrapping_add(disp << 1);
+        self.regs.gpr[0] = self.read_word(bus, addr) as i16 as i32 as u32;
+    }
+
+    // CMP/EQ #imm,R0  10001000iiiiiiii  If R0 = imm, 1 → T          1    Comp.
+    //                                                                    result
+    fn cmp_eq_i(&mut self, imm: u32) {
+        // the immediate is sign extended for this one
+        self.regs.sr_t = self.regs.gpr[0] == imm as i8 as i32 as u32;
+    }
+
+    // BT label  10001001dddddddd  If T = 1, disp × 2 + PC → PC;      3/1  -
+    //                             if T = 0, nop
+    fn bt(&mut self, disp: i32) {
+        if self.regs.sr_t {
+            self.regs.pc = self.regs.pc.wrapping_add(2)
+                                       .wrapping_add((disp << 1) as u32);
+            self.cycles += 2;
+        }
+    }
+
     // BF  label  10001011dddddddd  If T = 0, disp × 2 + PC → PC;     3/1  -
     //                              if T = 1, nop
     fn bf(&mut self, disp: i32) {
         if !self.regs.sr_t {
-            self.regs.pc = (self.regs.pc + 2).wrapping_add((disp << 1) as u32);
+            self.regs.pc = self.regs.pc.wrapping_add(2)
+                                       .wrapping_add((disp << 1) as u32);
             self.cycles += 2;
         }
     }
 
+    // BT/S label  10001101dddddddd  If T = 1, disp × 2 + PC → PC;    2/1  -
+    //                               if T = 0, nop
+    fn bt_s(&mut self, disp: i32) {
+        if self.regs.sr_t {
+            let target = self.regs.pc.wrapping_add(2)
+                                     .wrapping_add((disp << 1) as u32);
+            self.delay_branch(target);
+            self.cycles += 1;
+        }
+    }
+
+    // BF/S label  10001111dddddddd  If T = 0, disp × 2 + PC → PC;    2/1  -
+    //                               if T = 1, nop
+    fn bf_s(&mut self, disp: i32) {
+        if !self.regs.sr_t {
+            let target = self.regs.pc.wrapping_add(2)
+                                     .wrapping_add((disp << 1) as u32);
+            self.delay_branch(target);
+            self.cycles += 1;
+        }
+    }
+
 
     // 1001
     // MOV.W @(disp:8,PC),Rn  1001nnnndddddddd  (disp × 2 + PC) →     1    -
     //                                           Sign extension → Rn
     fn mov_wi<B: Bus>(&mut self, bus: &mut B, disp: u32, rn: usize) {
-        // PC = 4 bytes past current instr
-        let src = (disp << 1) + self.regs.pc + 2;
-        let val = bus.read_word(src) as i16 as i32 as u32;
+        let src = (disp << 1).wrapping_add(self.pc_rel());
+        let val = self.read_word(bus, src) as i16 as i32 as u32;
         self.regs.gpr[rn] = val;
     }
 
@@ -350,19 +1647,145 @@ impl Sh2 {
     // BRA label  1010dddddddddddd  Delayed branch,                   2    -
     //                              disp × 2 + PC → PC
     fn bra(&mut self, disp: i32) {
-        self.delay = true;
-        self.delay_pc = (self.regs.pc + 2).wrapping_add((disp << 1) as u32);
+        let target = self.regs.pc.wrapping_add(2)
+                                 .wrapping_add((disp << 1) as u32);
+        self.delay_branch(target);
         self.cycles += 1;
     }
 
 
+    // 1011
+    // BSR label  1011dddddddddddd  Delayed branch, PC → PR,          2    -
+    //                              disp × 2 + PC → PC
+    fn bsr(&mut self, disp: i32) {
+        let pc = self.regs.pc.wrapping_add(2);
+        self.regs.pr = pc;
+        self.delay_branch(pc.wrapping_add((disp << 1) as u32));
+        self.cycles += 1;
+    }
+
+
+    // 1100
+    // MOV.B R0,@(disp:8,GBR)  11000000dddddddd  R0 → (disp + GBR)    1    -
+    fn mov_bsg<B: Bus>(&mut self, bus: &mut B, disp: u32) {
+        let addr = self.regs.gbr.wrapping_add(disp);
+        self.write_byte(bus, addr, self.regs.gpr[0] as u8);
+    }
+
+    // MOV.W R0,@(disp:8,GBR)  11000001dddddddd  R0 → (disp × 2 +     1    -
+    //                                           GBR)
+    fn mov_wsg<B: Bus>(&mut self, bus: &mut B, disp: u32) {
+        let addr = self.regs.gbr.wrapping_add(disp << 1);
+        self.write_word(bus, addr, self.regs.gpr[0] as u16);
+    }
+
+    // MOV.L R0,@(disp:8,GBR)  11000010dddddddd  R0 → (disp × 4 +     1    -
+    //                                           GBR)
+    fn mov_lsg<B: Bus>(&mut self, bus: &mut B, disp: u32) {
+        let addr = self.regs.gbr.wrapping_add(disp << 2);
+        self.write_long(bus, addr, self.regs.gpr[0]);
+    }
+
+    // TRAPA #imm  11000011iiiiiiii  PC/SR → Stack area,              8    -
+    //                               (imm × 4 + VBR) → PC
+    fn trapa<B: Bus>(&mut self, bus: &mut B, imm: u32) {
+        // the return address is the instr after TRAPA
+        let ret_pc = self.regs.pc;
+        self.exception(bus, imm, ret_pc);
+    }
+
+    // MOV.B @(disp:8,GBR),R0  11000100dddddddd  (disp + GBR) → Sign  1    -
+    //                                           extension → R0
+    fn mov_blg<B: Bus>(&mut self, bus: &mut B, disp: u32) {
+        let addr = self.regs.gbr.wrapping_add(disp);
+        self.regs.gpr[0] = self.read_byte(bus, addr) as i8 as i32 as u32;
+    }
+
+    // MOV.W @(disp:8,GBR),R0  11000101dddddddd  (disp × 2 + GBR) →   1    -
+    //                                           Sign extension → R0
+    fn mov_wlg<B: Bus>(&mut self, bus: &mut B, disp: u32) {
+        let addr = self.regs.gbr.wrapping_add(disp << 1);
+        self.regs.gpr[0] = self.read_word(bus, addr) as i16 as i32 as u32;
+    }
+
+    // MOV.L @(disp:8,GBR),R0  11000110dddddddd  (disp × 4 + GBR) →   1    -
+    //                                           R0
+    fn mov_llg<B: Bus>(&mut self, bus: &mut B, disp: u32) {
+        let addr = self.regs.gbr.wrapping_add(disp << 2);
+        self.regs.gpr[0] = self.read_long(bus, addr);
+    }
+
+    // MOVA @(disp:8,PC),R0  11000111dddddddd  disp × 4 + PC → R0     1    -
+    fn mova<B: Bus>(&mut self, _bus: &mut B, disp: u32) {
+        // with bottom 2 bits of PC set to 0
+        let pc = self.pc_rel() & 0xfffffffc;
+        self.regs.gpr[0] = pc.wrapping_add(disp << 2);
+    }
+
+    // TST #imm,R0  11001000iiiiiiii  R0 & imm; if the result is 0,   1    Test
+    //                                1 → T                               result
+    fn tst_i(&mut self, imm: u32) {
+        self.regs.sr_t = self.regs.gpr[0] & imm == 0;
+    }
+
+    // AND #imm,R0  11001001iiiiiiii  R0 & imm → R0                   1    -
+    fn and_i(&mut self, imm: u32) {
+        self.regs.gpr[0] &= imm;
+    }
+
+    // XOR #imm,R0  11001010iiiiiiii  R0 ^ imm → R0                   1    -
+    fn xor_i(&mut self, imm: u32) {
+        self.regs.gpr[0] ^= imm;
+    }
+
+    // OR #imm,R0  11001011iiiiiiii  R0 | imm → R0                    1    -
+    fn or_i(&mut self, imm: u32) {
+        self.regs.gpr[0] |= imm;
+    }
+
+    // TST.B #imm,@(R0,GBR)  11001100iiiiiiii  (R0 + GBR) & imm; if   3    Test
+    //                                         the result is 0, 1 → T     result
+    fn tst_b<B: Bus>(&mut self, bus: &mut B, imm: u32) {
+        let addr = self.regs.gbr.wrapping_add(self.regs.gpr[0]);
+        self.regs.sr_t = self.read_byte(bus, addr) as u32 & imm == 0;
+        self.cycles += 2;
+    }
+
+    // AND.B #imm,@(R0,GBR)  11001101iiiiiiii  (R0 + GBR) & imm →     3    -
+    //                                         (R0 + GBR)
+    fn and_b<B: Bus>(&mut self, bus: &mut B, imm: u32) {
+        let addr = self.regs.gbr.wrapping_add(self.regs.gpr[0]);
+        let val = self.read_byte(bus, addr) & imm as u8;
+        self.write_byte(bus, addr, val);
+        self.cycles += 2;
+    }
+
+    // XOR.B #imm,@(R0,GBR)  11001110iiiiiiii  (R0 + GBR) ^ imm →     3    -
+    //                                         (R0 + GBR)
+    fn xor_b<B: Bus>(&mut self, bus: &mut B, imm: u32) {
+        let addr = self.regs.gbr.wrapping_add(self.regs.gpr[0]);
+        let val = self.read_byte(bus, addr) ^ imm as u8;
+        self.write_byte(bus, addr, val);
+        self.cycles += 2;
+    }
+
+    // OR.B #imm,@(R0,GBR)  11001111iiiiiiii  (R0 + GBR) | imm →      3    -
+    //                                        (R0 + GBR)
+    fn or_b<B: Bus>(&mut self, bus: &mut B, imm: u32) {
+        let addr = self.regs.gbr.wrapping_add(self.regs.gpr[0]);
+        let val = self.read_byte(bus, addr) | imm as u8;
+        self.write_byte(bus, addr, val);
+        self.cycles += 2;
+    }
+
+
     // 1101
     // MOV.L @(disp:8,PC),Rn  1101nnnndddddddd  (disp × 4 + PC) → Rn  1    -
     fn mov_li<B: Bus>(&mut self, bus: &mut B, disp: u32, rn: usize) {
-        // PC = 4 bytes past current instr, with bottom 2 bits set to 0
-        let pc = (self.regs.pc + 2) & 0xfffffffc;
-        let src = (disp << 2) + pc;
-        self.regs.gpr[rn] = bus.read_long(src);
+        // with bottom 2 bits of PC set to 0
+        let pc = self.pc_rel() & 0xfffffffc;
+        let src = pc.wrapping_add(disp << 2);
+        self.regs.gpr[rn] = self.read_long(bus, src);
     }
 
 
@@ -377,10 +1800,57 @@ impl Sh2 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bus::BusFault;
     use common::MemAccess;
 
     pub struct TestBus {
-        mem: [u8; 4]
+        mem: Vec<u8>,
+        nmi: bool,
+        fault: Option<BusFault>,
+        irq: Option<(u32, u32)>,
+        ticks: u64,
+        stall: u64,
+    }
+
+    impl TestBus {
+        fn new(mem: Vec<u8>) -> TestBus {
+            TestBus { mem, nmi: false, fault: None, irq: None, ticks: 0,
+                      stall: 0 }
+        }
+
+        // 64KB of zeroed memory with prog loaded at address 0
+        fn with_prog(prog: &[u16]) -> TestBus {
+            let mut bus = TestBus::new(vec![0; 0x10000]);
+            for (i, op) in prog.iter().enumerate() {
+                u16::write_mem(&mut bus.mem, i * 2, *op);
+            }
+            bus
+        }
+    }
+
+    // cpu reset to the start of prog, with the stack at the top of mem
+    fn setup(prog: &[u16]) -> (Sh2, TestBus) {
+        let bus = TestBus::with_prog(prog);
+        let mut cpu = Sh2::new();
+        cpu.reset(0x00000000, 0x00010000);
+        (cpu, bus)
+    }
+
+    fn run(cpu: &mut Sh2, bus: &mut TestBus, steps: usize) {
+        for _ in 0..steps {
+            cpu.step(bus).unwrap();
+        }
+    }
+
+    // execute a single op encoded with rm = r1 and rn = r2, returning
+    // (rn, t) afterwards
+    fn exec_mn(op: u16, rm: u32, rn: u32, t: bool) -> (u32, bool) {
+        let (mut cpu, mut bus) = setup(&[op]);
+        cpu.regs.gpr[1] = rm;
+        cpu.regs.gpr[2] = rn;
+        cpu.regs.sr_t = t;
+        cpu.step(&mut bus).unwrap();
+        (cpu.regs.gpr[2], cpu.regs.sr_t)
     }
 
     impl Bus for TestBus {
@@ -407,28 +1877,1143 @@ mod tests {
         fn write_long(&mut self, addr: u32, val: u32) {
             u32::write_mem(&mut self.mem, addr as usize, val);
         }
+
+        fn take_nmi(&mut self) -> bool {
+            let nmi = self.nmi;
+            self.nmi = false;
+            nmi
+        }
+
+        fn take_fault(&mut self) -> Option<BusFault> {
+            self.fault.take()
+        }
+
+        fn tick(&mut self, cycles: u64) {
+            self.ticks += cycles;
+        }
+
+        fn pending_irq(&self) -> Option<(u32, u32)> {
+            self.irq
+        }
+
+        fn take_stall(&mut self) -> u64 {
+            let stall = self.stall;
+            self.stall = 0;
+            stall
+        }
     }
 
     #[test]
     fn read_a_word() {
-        let bus = TestBus { mem: [0xff, 0xee, 0xdd, 0xcc] };
+        let bus = TestBus::new(vec![0xff, 0xee, 0xdd, 0xcc]);
         assert_eq!(bus.read_word(0), 0xffee);
     }
 
     #[test]
     fn write_a_long() {
-        let mut bus = TestBus { mem: [0x00, 0x00, 0x00, 0x11] };
+        let mut bus = TestBus::new(vec![0x00, 0x00, 0x00, 0x11]);
         bus.write_long(0, 0xffeeddcc);
         assert_eq!(bus.mem, [0xff, 0xee, 0xdd, 0xcc]);
     }
 
     #[test]
     fn test_step_pc() {           //  and r1, r4  or r2, r4
-        let mut bus = TestBus { mem: [0x24, 0x19, 0x24, 0x2b] };
+        let mut bus = TestBus::new(vec![0x24, 0x19, 0x24, 0x2b]);
         let mut cpu = Sh2::new();
         cpu.reset(0x00000000, 0x1000000);
-        cpu.step(&mut bus);
+        cpu.step(&mut bus).unwrap();
         println!("pc: {}", cpu.regs.pc);
         assert_eq!(cpu.regs.pc, 0x00000002);
     }
+
+    #[test]
+    fn mov_post_inc_sign_extends() {
+        let (mut cpu, mut bus) = setup(&[0x6315,     // mov.w @r1+, r3
+                                         0x6214]);   // mov.b @r1+, r2
+        u16::write_mem(&mut bus.mem, 0x100, 0x8001);
+        bus.mem[0x102] = 0x80;
+        cpu.regs.gpr[1] = 0x100;
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.regs.gpr[2], 0xffffff80);
+        assert_eq!(cpu.regs.gpr[3], 0xffff8001);
+        assert_eq!(cpu.regs.gpr[1], 0x103);
+    }
+
+    #[test]
+    fn mov_post_inc_same_reg() {
+        let (mut cpu, mut bus) = setup(&[0x6116]);   // mov.l @r1+, r1
+        u32::write_mem(&mut bus.mem, 0x100, 0x12345678);
+        cpu.regs.gpr[1] = 0x100;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.gpr[1], 0x12345678);
+    }
+
+    #[test]
+    fn mov_pre_dec() {
+        let (mut cpu, mut bus) = setup(&[0x2125,     // mov.w r2, @-r1
+                                         0x2124]);   // mov.b r2, @-r1
+        cpu.regs.gpr[1] = 0x104;
+        cpu.regs.gpr[2] = 0x1234abcd;
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.regs.gpr[1], 0x101);
+        assert_eq!(&bus.mem[0x101..0x104], &[0xcd, 0xab, 0xcd]);
+    }
+
+    #[test]
+    fn mov_disp_and_r0_indexed() {
+        let (mut cpu, mut bus) = setup(&[0x1123,     // mov.l r2, @(12, r1)
+                                         0x5313,     // mov.l @(12, r1), r3
+                                         0x8012,     // mov.b r0, @(2, r1)
+                                         0x8512,     // mov.w @(4, r1), r0
+                                         0x0124,     // mov.b r2, @(r0, r1)
+                                         0x041c]);   // mov.b @(r0, r1), r4
+        cpu.regs.gpr[0] = 0x7f;
+        cpu.regs.gpr[1] = 0x100;
+        cpu.regs.gpr[2] = 0xcafef00d;
+        u16::write_mem(&mut bus.mem, 0x104, 0x7f00);
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(u32::read_mem(&bus.mem, 0x10c), 0xcafef00d);
+        assert_eq!(cpu.regs.gpr[3], 0xcafef00d);
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(bus.mem[0x102], 0x7f);
+        assert_eq!(cpu.regs.gpr[0], 0x7f00);
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(bus.mem[0x7f00 + 0x100], 0x0d);
+        assert_eq!(cpu.regs.gpr[4], 0x0d);
+    }
+
+    #[test]
+    fn mov_gbr_relative() {
+        let (mut cpu, mut bus) = setup(&[0xc202,     // mov.l r0, @(8, gbr)
+                                         0xc581,     // mov.w @(0x102, gbr), r0
+                                         0xc406]);   // mov.b @(6, gbr), r0
+        cpu.regs.gbr = 0x200;
+        cpu.regs.gpr[0] = 0x8899aabb;
+        u16::write_mem(&mut bus.mem, 0x302, 0x8123);
+        run(&mut cpu, &mut bus, 1);
+        assert_eq!(u32::read_mem(&bus.mem, 0x208), 0x8899aabb);
+        run(&mut cpu, &mut bus, 1);
+        assert_eq!(cpu.regs.gpr[0], 0xffff8123);
+        run(&mut cpu, &mut bus, 1);
+        assert_eq!(cpu.regs.gpr[0], 0x00000000);
+    }
+
+    #[test]
+    fn mova_movt_swap_xtrct() {
+        let (mut cpu, mut bus) = setup(&[0x0009,     // (unused)
+                                         0xc702,     // mova @(8, PC), r0
+                                         0x0129,     // movt r1
+                                         0x6238,     // swap.b r3, r2
+                                         0x6439,     // swap.w r3, r4
+                                         0x253d]);   // xtrct r3, r5
+        cpu.regs.pc = 2;
+        cpu.regs.sr_t = true;
+        cpu.regs.gpr[3] = 0x11223344;
+        cpu.regs.gpr[5] = 0xaabbccdd;
+        run(&mut cpu, &mut bus, 5);
+        // (pc + 4) & ~3 + 8
+        assert_eq!(cpu.regs.gpr[0], 0x0000000c);
+        assert_eq!(cpu.regs.gpr[1], 1);
+        assert_eq!(cpu.regs.gpr[2], 0x11224433);
+        assert_eq!(cpu.regs.gpr[4], 0x33441122);
+        assert_eq!(cpu.regs.gpr[5], 0x3344aabb);
+    }
+
+    #[test]
+    fn add_sub() {
+        assert_eq!(exec_mn(0x321c, 3, 0xffffffff, true), (2, true));
+        assert_eq!(exec_mn(0x3218, 3, 1, true), (0xfffffffe, true));
+    }
+
+    #[test]
+    fn addc() {
+        assert_eq!(exec_mn(0x321e, 1, 1, true), (3, false));
+        assert_eq!(exec_mn(0x321e, 0, 0xffffffff, true), (0, true));
+        assert_eq!(exec_mn(0x321e, 1, 0xffffffff, false), (0, true));
+        assert_eq!(exec_mn(0x321e, 0xffffffff, 0xffffffff, true),
+                   (0xffffffff, true));
+    }
+
+    #[test]
+    fn addv() {
+        assert_eq!(exec_mn(0x321f, 1, 0x7ffffffe, false), (0x7fffffff, false));
+        assert_eq!(exec_mn(0x321f, 2, 0x7ffffffe, false), (0x80000000, true));
+        assert_eq!(exec_mn(0x321f, 0xffffffff, 0x80000000, false),
+                   (0x7fffffff, true));
+    }
+
+    #[test]
+    fn subc() {
+        assert_eq!(exec_mn(0x321a, 1, 3, true), (1, false));
+        assert_eq!(exec_mn(0x321a, 0, 0, true), (0xffffffff, true));
+        assert_eq!(exec_mn(0x321a, 1, 0, false), (0xffffffff, true));
+    }
+
+    #[test]
+    fn subv() {
+        assert_eq!(exec_mn(0x321b, 2, 0x80000001, false), (0x7fffffff, true));
+        assert_eq!(exec_mn(0x321b, 0xfffffffe, 0x7ffffffe, false),
+                   (0x80000000, true));
+        assert_eq!(exec_mn(0x321b, 1, 0, false), (0xffffffff, false));
+    }
+
+    #[test]
+    fn neg_negc() {
+        assert_eq!(exec_mn(0x621b, 1, 0, true), (0xffffffff, true));
+        assert_eq!(exec_mn(0x621a, 1, 0, false), (0xffffffff, true));
+        assert_eq!(exec_mn(0x621a, 0, 0, false), (0, false));
+        assert_eq!(exec_mn(0x621a, 0, 0, true), (0xffffffff, true));
+    }
+
+    #[test]
+    fn dt() {
+        let (mut cpu, mut bus) = setup(&[0x4210, 0x4210]);  // dt r2; dt r2
+        cpu.regs.gpr[2] = 2;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.gpr[2], cpu.regs.sr_t), (1, false));
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.gpr[2], cpu.regs.sr_t), (0, true));
+    }
+
+    #[test]
+    fn cmp_eq() {
+        assert!(exec_mn(0x3210, 5, 5, false).1);
+        assert!(!exec_mn(0x3210, 5, 6, true).1);
+    }
+
+    #[test]
+    fn cmp_eq_imm() {
+        let (mut cpu, mut bus) = setup(&[0x88fe]);  // cmp/eq #-2, r0
+        cpu.regs.gpr[0] = 0xfffffffe;
+        cpu.step(&mut bus).unwrap();
+        assert!(cpu.regs.sr_t);
+    }
+
+    #[test]
+    fn cmp_hs_hi() {
+        assert!(exec_mn(0x3212, 5, 5, false).1);
+        assert!(!exec_mn(0x3216, 5, 5, true).1);
+        assert!(exec_mn(0x3216, 1, 0xffffffff, false).1);
+    }
+
+    #[test]
+    fn cmp_ge_gt() {
+        assert!(exec_mn(0x3213, 5, 5, false).1);
+        assert!(!exec_mn(0x3213, 1, 0xffffffff, true).1);
+        assert!(!exec_mn(0x3217, 5, 5, true).1);
+        assert!(exec_mn(0x3217, 0xffffffff, 0, false).1);
+    }
+
+    #[test]
+    fn cmp_pz_pl() {
+        assert!(exec_mn(0x4211, 0, 0, false).1);
+        assert!(!exec_mn(0x4211, 0, 0x80000000, true).1);
+        assert!(!exec_mn(0x4215, 0, 0, true).1);
+        assert!(exec_mn(0x4215, 0, 1, false).1);
+    }
+
+    #[test]
+    fn cmp_str() {
+        assert!(exec_mn(0x221c, 0x11223344, 0x55663377, false).1);
+        assert!(!exec_mn(0x221c, 0x11223344, 0x44332211, true).1);
+    }
+
+    // R1:R2 (64 bits) ÷ R0 (32 bits) = R2 (32 bits), unsigned. The
+    // sequence from the DIV1 examples of the programming manual, which
+    // expects R1 < R0.
+    fn divu_64_32(dividend: u64, divisor: u32) -> u32 {
+        let mut cpu = Sh2::new();
+        cpu.regs.gpr[0] = divisor;
+        cpu.regs.gpr[1] = (dividend >> 32) as u32;
+        cpu.regs.gpr[2] = dividend as u32;
+        cpu.div0u();
+        for _ in 0..32 {
+            cpu.rotcl(2);
+            cpu.div1(0, 1);
+        }
+        cpu.rotcl(2);
+        cpu.regs.gpr[2]
+    }
+
+    // R1 (32 bits) ÷ R0 (32 bits) = R1 (32 bits), signed. This is the
+    // sequence of libgcc's __sdivsi3.
+    fn divs_32_32(dividend: i32, divisor: i32) -> i32 {
+        let mut cpu = Sh2::new();
+        cpu.regs.gpr[0] = divisor as u32;
+        cpu.regs.gpr[1] = dividend as u32;
+        cpu.regs.gpr[2] = 0;
+        cpu.div0s(2, 1);
+        cpu.subc(3, 3);
+        cpu.subc(2, 1);
+        cpu.div0s(0, 3);
+        for _ in 0..32 {
+            cpu.rotcl(1);
+            cpu.div1(0, 3);
+        }
+        cpu.rotcl(1);
+        cpu.addc(2, 1);
+        cpu.regs.gpr[1] as i32
+    }
+
+    #[test]
+    fn div0s_div0u() {
+        let (mut cpu, mut bus) = setup(&[0x2217,     // div0s r1, r2
+                                         0x0019]);   // div0u
+        cpu.regs.gpr[1] = 0x80000000;
+        cpu.regs.gpr[2] = 0x00000001;
+        cpu.step(&mut bus).unwrap();
+        assert!(!cpu.regs.sr_q && cpu.regs.sr_m && cpu.regs.sr_t);
+        cpu.step(&mut bus).unwrap();
+        assert!(!cpu.regs.sr_q && !cpu.regs.sr_m && !cpu.regs.sr_t);
+    }
+
+    #[test]
+    fn div1_unsigned_32_32() {
+        let cases = [(100, 7), (0xffffffff, 1), (0xffffffff, 0xffffffff),
+                     (0, 5), (12345678, 1000), (0x80000000, 3)];
+        for &(a, b) in cases.iter() {
+            assert_eq!(divu_64_32(a as u64, b), a / b, "{} / {}", a, b);
+        }
+    }
+
+    #[test]
+    fn div1_unsigned_64_32() {
+        let cases = [(0x0000_0001_0000_0000, 2), (0x1234_5678_9abc_def0,
+                      0x8000_0001), (0x7fff_ffff_ffff_ffff, 0xffff_ffff)];
+        for &(a, b) in cases.iter() {
+            assert_eq!(divu_64_32(a, b) as u64, a / b as u64,
+                       "{} / {}", a, b);
+        }
+    }
+
+    #[test]
+    fn div1_signed_32_32() {
+        let cases = [(100, 7), (-100, 7), (100, -7), (-100, -7), (0, -3),
+                     (i32::MAX, 2), (i32::MIN + 1, -1), (-1, 1),
+                     (-123456789, 1000)];
+        for &(a, b) in cases.iter() {
+            assert_eq!(divs_32_32(a, b), a / b, "{} / {}", a, b);
+        }
+    }
+
+    #[test]
+    fn mul_l_and_w() {
+        let (mut cpu, mut bus) = setup(&[0x0217,     // mul.l r1, r2
+                                         0x221f,     // muls.w r1, r2
+                                         0x221e]);   // mulu.w r1, r2
+        cpu.regs.gpr[1] = 0xfffffffe;
+        cpu.regs.gpr[2] = 0x00010003;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.macl, 0xfffdfffa);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.macl, 0xfffffffa);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.macl, 0x0002fffa);
+    }
+
+    #[test]
+    fn dmul() {
+        let (mut cpu, mut bus) = setup(&[0x321d,     // dmuls.l r1, r2
+                                         0x3215]);   // dmulu.l r1, r2
+        cpu.regs.gpr[1] = 0xfffffffe;
+        cpu.regs.gpr[2] = 0x80000000;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.mach, cpu.regs.macl), (0x00000001, 0x00000000));
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.mach, cpu.regs.macl), (0x7fffffff, 0x00000000));
+    }
+
+    #[test]
+    fn mac_w() {
+        let (mut cpu, mut bus) = setup(&[0x0028,     // clrmac
+                                         0x421f,     // mac.w @r1+, @r2+
+                                         0x421f]);   // mac.w @r1+, @r2+
+        u16::write_mem(&mut bus.mem, 0x100, 0xfffe);
+        u16::write_mem(&mut bus.mem, 0x102, 0x0003);
+        u16::write_mem(&mut bus.mem, 0x200, 0x0005);
+        u16::write_mem(&mut bus.mem, 0x202, 0x0007);
+        cpu.regs.sr_s = false;
+        cpu.regs.gpr[1] = 0x100;
+        cpu.regs.gpr[2] = 0x200;
+        run(&mut cpu, &mut bus, 3);
+        assert_eq!(cpu.mac() as i64, -10 + 21);
+        assert_eq!((cpu.regs.gpr[1], cpu.regs.gpr[2]), (0x104, 0x204));
+    }
+
+    #[test]
+    fn mac_w_saturates() {
+        let (mut cpu, mut bus) = setup(&[0x421f]);   // mac.w @r1+, @r2+
+        u16::write_mem(&mut bus.mem, 0x100, 0x7fff);
+        u16::write_mem(&mut bus.mem, 0x200, 0x7fff);
+        cpu.regs.sr_s = true;
+        cpu.regs.mach = 0;
+        cpu.regs.macl = 0x7ff00000;
+        cpu.regs.gpr[1] = 0x100;
+        cpu.regs.gpr[2] = 0x200;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.mach, cpu.regs.macl), (1, 0x7fffffff));
+    }
+
+    #[test]
+    fn mac_l() {
+        let (mut cpu, mut bus) = setup(&[0x021f,     // mac.l @r1+, @r2+
+                                         0x021f]);   // mac.l @r1+, @r2+
+        u32::write_mem(&mut bus.mem, 0x100, 0x7fffffff);
+        u32::write_mem(&mut bus.mem, 0x104, 0x7fffffff);
+        u32::write_mem(&mut bus.mem, 0x200, 0x7fffffff);
+        u32::write_mem(&mut bus.mem, 0x204, 0x7fffffff);
+        cpu.regs.gpr[1] = 0x100;
+        cpu.regs.gpr[2] = 0x200;
+        cpu.regs.sr_s = false;
+        cpu.set_mac(0);
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.mac(), 2 * 0x3fffffff_00000001);
+
+        // and now saturated at 48 bits
+        cpu.regs.pc = 0;
+        cpu.regs.gpr[1] = 0x100;
+        cpu.regs.gpr[2] = 0x200;
+        cpu.regs.sr_s = true;
+        cpu.set_mac(0);
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.mac(), 0x00007fff_ffffffff);
+    }
+
+    #[test]
+    fn sts_lds_mac() {
+        let (mut cpu, mut bus) = setup(&[0x4112,     // sts.l macl, @-r1
+                                         0x4102,     // sts.l mach, @-r1
+                                         0x0028,     // clrmac
+                                         0x4106,     // lds.l @r1+, mach
+                                         0x4116,     // lds.l @r1+, macl
+                                         0x020a,     // sts mach, r2
+                                         0x431a]);   // lds r3, macl
+        cpu.regs.mach = 0x11111111;
+        cpu.regs.macl = 0x22222222;
+        cpu.regs.gpr[1] = 0x108;
+        cpu.regs.gpr[3] = 0x33333333;
+        run(&mut cpu, &mut bus, 3);
+        assert_eq!(cpu.mac(), 0);
+        run(&mut cpu, &mut bus, 4);
+        assert_eq!(cpu.regs.gpr[1], 0x108);
+        assert_eq!(cpu.regs.gpr[2], 0x11111111);
+        assert_eq!((cpu.regs.mach, cpu.regs.macl), (0x11111111, 0x33333333));
+    }
+
+    #[test]
+    fn shifts() {
+        assert_eq!(exec_mn(0x4200, 0, 0xc0000001, false), (0x80000002, true));
+        assert_eq!(exec_mn(0x4201, 0, 0x80000003, false), (0x40000001, true));
+        assert_eq!(exec_mn(0x4220, 0, 0x40000001, true), (0x80000002, false));
+        assert_eq!(exec_mn(0x4221, 0, 0x80000002, true), (0xc0000001, false));
+        assert_eq!(exec_mn(0x4208, 0, 0x12345678, true), (0x48d159e0, true));
+        assert_eq!(exec_mn(0x4209, 0, 0x12345678, false), (0x048d159e, false));
+        assert_eq!(exec_mn(0x4218, 0, 0x12345678, false), (0x34567800, false));
+        assert_eq!(exec_mn(0x4219, 0, 0x12345678, false), (0x00123456, false));
+        assert_eq!(exec_mn(0x4228, 0, 0x12345678, false), (0x56780000, false));
+        assert_eq!(exec_mn(0x4229, 0, 0x12345678, false), (0x00001234, false));
+    }
+
+    #[test]
+    fn rotates() {
+        assert_eq!(exec_mn(0x4204, 0, 0x80000002, false), (0x00000005, true));
+        assert_eq!(exec_mn(0x4205, 0, 0x00000005, false), (0x80000002, true));
+        assert_eq!(exec_mn(0x4224, 0, 0x80000002, false), (0x00000004, true));
+        assert_eq!(exec_mn(0x4224, 0, 0x00000002, true), (0x00000005, false));
+        assert_eq!(exec_mn(0x4225, 0, 0x00000005, false), (0x00000002, true));
+        assert_eq!(exec_mn(0x4225, 0, 0x00000004, true), (0x80000002, false));
+    }
+
+    #[test]
+    fn not() {
+        assert_eq!(exec_mn(0x6217, 0x0000ffff, 0, false), (0xffff0000, false));
+    }
+
+    #[test]
+    fn logic_imm() {
+        let (mut cpu, mut bus) = setup(&[0xc80f,     // tst #0x0f, r0
+                                         0xc9f0,     // and #0xf0, r0
+                                         0xcb81,     // or #0x81, r0
+                                         0xcaff]);   // xor #0xff, r0
+        cpu.regs.gpr[0] = 0xfffff0f0;
+        cpu.step(&mut bus).unwrap();
+        assert!(cpu.regs.sr_t);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.gpr[0], 0x000000f0);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.gpr[0], 0x000000f1);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.gpr[0], 0x0000000e);
+    }
+
+    #[test]
+    fn logic_gbr_byte() {
+        let (mut cpu, mut bus) = setup(&[0xcc01,     // tst.b #1, @(r0, gbr)
+                                         0xcd0f,     // and.b #0x0f, @(r0, gbr)
+                                         0xcf80,     // or.b #0x80, @(r0, gbr)
+                                         0xce01]);   // xor.b #0x01, @(r0, gbr)
+        cpu.regs.gbr = 0x100;
+        cpu.regs.gpr[0] = 0x10;
+        bus.mem[0x110] = 0x3c;
+        cpu.step(&mut bus).unwrap();
+        assert!(cpu.regs.sr_t);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(bus.mem[0x110], 0x0c);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(bus.mem[0x110], 0x8c);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(bus.mem[0x110], 0x8d);
+    }
+
+    #[test]
+    fn tas_b() {
+        let (mut cpu, mut bus) = setup(&[0x421b,     // tas.b @r2
+                                         0x421b]);   // tas.b @r2
+        cpu.regs.gpr[2] = 0x100;
+        cpu.step(&mut bus).unwrap();
+        assert!(cpu.regs.sr_t);
+        assert_eq!(bus.mem[0x100], 0x80);
+        cpu.step(&mut bus).unwrap();
+        assert!(!cpu.regs.sr_t);
+        assert_eq!(cpu.cycles, 8);
+    }
+
+    // delay slots
+
+    #[test]
+    fn bra_executes_slot_first() {
+        let (mut cpu, mut bus) = setup(&[0xa002,     // bra 0x8
+                                         0x7101,     // add #1, r1 (slot)
+                                         0x7110,     // add #0x10, r1
+                                         0x7110,     // add #0x10, r1
+                                         0x6313]);   // mov r1, r3
+        cpu.regs.gpr[1] = 0;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 2);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[1]), (8, 1));
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.gpr[3], 1);
+        assert_eq!(cpu.cycles, 2 + 1 + 1);
+    }
+
+    #[test]
+    fn bsr_rts_round_trip() {
+        let mut prog = [0x0009; 10];
+        prog[0] = 0xb006;                            // bsr 0x10
+        prog[1] = 0xe101;                            // mov #1, r1 (slot)
+        prog[2] = 0x7102;                            // add #2, r1
+        prog[8] = 0x000b;                            // rts
+        prog[9] = 0x7104;                            // add #4, r1 (slot)
+        let (mut cpu, mut bus) = setup(&prog);
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.regs.pc, cpu.regs.pr), (0x10, 4));
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[1]), (4, 5));
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.gpr[1], 7);
+        assert_eq!(cpu.cycles, 2 + 1 + 2 + 1 + 1);
+    }
+
+    #[test]
+    fn jsr_jmp_braf_bsrf() {
+        let mut prog = [0x0009; 0x30];
+        prog[0] = 0x430b;                            // jsr @r3 -> 0x20
+        prog[1] = 0x7101;                            // add #1, r1 (slot)
+        prog[0x10] = 0x442b;                         // jmp @r4 -> 0x30
+        prog[0x11] = 0x7102;                         // add #2, r1 (slot)
+        prog[0x18] = 0x0523;                         // braf r5 -> 0x40
+        prog[0x19] = 0x7104;                         // add #4, r1 (slot)
+        prog[0x20] = 0x0503;                         // bsrf r5 -> 0x50
+        prog[0x21] = 0x7108;                         // add #8, r1 (slot)
+        let (mut cpu, mut bus) = setup(&prog);
+        cpu.regs.gpr[1] = 0;
+        cpu.regs.gpr[3] = 0x20;
+        cpu.regs.gpr[4] = 0x30;
+        cpu.regs.gpr[5] = 0x0c;
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.regs.pc, cpu.regs.pr, cpu.regs.gpr[1]), (0x20, 4, 1));
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[1]), (0x30, 3));
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[1]), (0x40, 7));
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.regs.pc, cpu.regs.pr, cpu.regs.gpr[1]),
+                   (0x50, 0x44, 15));
+        assert_eq!(cpu.cycles, 4 * (2 + 1));
+    }
+
+    #[test]
+    fn bt_bf_cycles() {
+        let (mut cpu, mut bus) = setup(&[0x8901,     // bt 0x6
+                                         0x8b01,     // bf 0x8
+                                         0x0009,
+                                         0x0009]);
+        cpu.regs.sr_t = false;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.pc, cpu.cycles), (2, 1));
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.pc, cpu.cycles), (8, 4));
+    }
+
+    #[test]
+    fn bt_s_bf_s() {
+        let (mut cpu, mut bus) = setup(&[0x8d02,     // bt/s 0x8
+                                         0x7101,     // add #1, r1
+                                         0x8f02,     // bf/s 0xc
+                                         0x7102,     // add #2, r1 (slot)
+                                         0x7104,     // add #4, r1
+                                         0x7108,     // add #8, r1
+                                         0x7110]);   // add #16, r1
+        cpu.regs.sr_t = false;
+        cpu.regs.gpr[1] = 0;
+        // not taken, no delay slot, 1 cycle each
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[1], cpu.cycles), (4, 1, 2));
+        // taken
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[1], cpu.cycles), (0xc, 3, 5));
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.gpr[1], 19);
+    }
+
+    #[test]
+    fn pc_relative_in_delay_slot() {
+        let mut prog = [0x0009; 0x14];
+        prog[0] = 0xa00e;                            // bra 0x20
+        prog[1] = 0xd201;                            // mov.l @(4, PC), r2
+        prog[2] = 0xa00d;                            // bra 0x22
+        prog[3] = 0xc701;                            // mova @(4, PC), r0
+        prog[0x12] = 0x1234;
+        prog[0x13] = 0x5678;
+        let (mut cpu, mut bus) = setup(&prog);
+        run(&mut cpu, &mut bus, 2);
+        // PC is the branch destination + 2 in the slot
+        assert_eq!(cpu.regs.gpr[2], 0x12345678);
+        cpu.regs.pc = 4;
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.regs.gpr[0], 0x28);
+    }
+
+    #[test]
+    fn ldc_stc() {
+        let (mut cpu, mut bus) = setup(&[0x410e,     // ldc r1, sr
+                                         0x0202,     // stc sr, r2
+                                         0x411e,     // ldc r1, gbr
+                                         0x412e,     // ldc r1, vbr
+                                         0x0312,     // stc gbr, r3
+                                         0x0422,     // stc vbr, r4
+                                         0x4f03,     // stc.l sr, @-r15
+                                         0x4f13,     // stc.l gbr, @-r15
+                                         0x4f23,     // stc.l vbr, @-r15
+                                         0x4f27,     // ldc.l @r15+, vbr
+                                         0x4f17,     // ldc.l @r15+, gbr
+                                         0x4f07]);   // ldc.l @r15+, sr
+        cpu.regs.gpr[1] = 0xffffffff;
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.regs.gpr[2], 0x3f3);
+        run(&mut cpu, &mut bus, 4);
+        assert_eq!((cpu.regs.gpr[3], cpu.regs.gpr[4]), (0xffffffff, 0xffffffff));
+        run(&mut cpu, &mut bus, 3);
+        assert_eq!(cpu.regs.gpr[15], 0x10000 - 12);
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 4), 0x3f3);
+        cpu.regs.gbr = 0;
+        cpu.regs.vbr = 0;
+        cpu.regs.sr_i = 0;
+        run(&mut cpu, &mut bus, 3);
+        assert_eq!(cpu.regs.gpr[15], 0x10000);
+        assert_eq!((cpu.regs.gbr, cpu.regs.vbr, cpu.regs.sr_i),
+                   (0xffffffff, 0xffffffff, 0xf));
+    }
+
+    #[test]
+    fn lds_sts_pr() {
+        let (mut cpu, mut bus) = setup(&[0x412a,     // lds r1, pr
+                                         0x4f22,     // sts.l pr, @-r15
+                                         0x0009,     // nop
+                                         0x4f26,     // lds.l @r15+, pr
+                                         0x022a]);   // sts pr, r2
+        cpu.regs.gpr[1] = 0x1234;
+        run(&mut cpu, &mut bus, 2);
+        cpu.regs.pr = 0;
+        run(&mut cpu, &mut bus, 3);
+        assert_eq!((cpu.regs.pr, cpu.regs.gpr[2]), (0x1234, 0x1234));
+    }
+
+    #[test]
+    fn clrt_sett() {
+        let (mut cpu, mut bus) = setup(&[0x0018,     // sett
+                                         0x0008]);   // clrt
+        cpu.step(&mut bus).unwrap();
+        assert!(cpu.regs.sr_t);
+        cpu.step(&mut bus).unwrap();
+        assert!(!cpu.regs.sr_t);
+    }
+
+    #[test]
+    fn sleep_halts() {
+        let (mut cpu, mut bus) = setup(&[0x001b,     // sleep
+                                         0x7101]);   // add #1, r1
+        cpu.regs.gpr[1] = 0;
+        run(&mut cpu, &mut bus, 10);
+        assert!(cpu.sleeping);
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[1]), (2, 0));
+    }
+
+    #[test]
+    fn trapa_rte() {
+        let mut prog = [0x0009; 0x30];
+        prog[0] = 0xc320;                            // trapa #0x20
+        prog[1] = 0x7101;                            // add #1, r1
+        prog[0x10] = 0x002b;                         // rte
+        prog[0x11] = 0x7102;                         // add #2, r1 (slot)
+        let (mut cpu, mut bus) = setup(&prog);
+        cpu.regs.vbr = 0x100;
+        u32::write_mem(&mut bus.mem, 0x100 + 0x20 * 4, 0x20);
+        cpu.regs.gpr[1] = 0;
+        cpu.regs.sr_t = true;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[15]), (0x20, 0x10000 - 8));
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 8), 2);
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 4), 0xf3);
+        assert_eq!(cpu.cycles, 8);
+        cpu.regs.sr_t = false;
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[15]), (2, 0x10000));
+        assert!(cpu.regs.sr_t);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.gpr[1], 3);
+        assert_eq!(cpu.cycles, 8 + 4 + 1 + 1);
+    }
+
+    #[test]
+    fn packed_sr() {
+        let mut cpu = Sh2::new();
+        cpu.set_sr(0xffffffff);
+        assert_eq!(cpu.get_sr(), 0x000003f3);
+        assert!(cpu.regs.sr_m && cpu.regs.sr_q && cpu.regs.sr_s && cpu.regs.sr_t);
+        assert_eq!(cpu.regs.sr_i, 0xf);
+        cpu.set_sr(0x00000152);
+        assert_eq!(cpu.get_regs().get_sr(), 0x00000152);
+        assert!(!cpu.regs.sr_m && cpu.regs.sr_q && cpu.regs.sr_s && !cpu.regs.sr_t);
+        assert_eq!(cpu.regs.sr_i, 0x5);
+    }
+
+    // exceptions
+
+    #[test]
+    fn general_illegal_instr() {
+        let (mut cpu, mut bus) = setup(&[0x0009,     // nop
+                                         0xffff]);   // undefined
+        cpu.regs.vbr = 0x100;
+        u32::write_mem(&mut bus.mem, 0x100 + 4 * 4, 0x40);
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[15]), (0x40, 0x10000 - 8));
+        // the stacked PC points at the illegal instr
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 8), 2);
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 4), cpu.get_sr());
+    }
+
+    #[test]
+    fn slot_illegal_instr() {
+        let (mut cpu, mut bus) = setup(&[0xa002,     // bra 0x8
+                                         0xa000]);   // bra in the slot
+        cpu.regs.vbr = 0x100;
+        u32::write_mem(&mut bus.mem, 0x100 + 6 * 4, 0x40);
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.regs.pc, 0x40);
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 8), 8);
+        // and no leftover delayed branch
+        assert!(!cpu.delay);
+    }
+
+    #[test]
+    fn address_error() {
+        let (mut cpu, mut bus) = setup(&[0x6212,     // mov.l @r1, r2
+                                         0x0009]);   // nop
+        cpu.regs.vbr = 0x100;
+        u32::write_mem(&mut bus.mem, 0x100 + 9 * 4, 0x40);
+        cpu.regs.gpr[1] = 0x202;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 0x40);
+        // the stacked PC is the next instr
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 8), 2);
+    }
+
+    #[test]
+    fn address_error_in_delayed_branch() {
+        let (mut cpu, mut bus) = setup(&[0x002b,     // rte, misaligned stack
+                                         0x7101]);   // add #1, r1 (slot)
+        cpu.regs.vbr = 0x100;
+        u32::write_mem(&mut bus.mem, 0x100 + 9 * 4, 0x40);
+        cpu.regs.gpr[1] = 0;
+        cpu.regs.gpr[15] = 0x8002;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 2);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.pc, cpu.regs.gpr[1]), (0x40, 1));
+    }
+
+    #[test]
+    fn strict_mode_stops() {
+        let (mut cpu, mut bus) = setup(&[0x0009, 0xffff]);
+        cpu.set_strict(true);
+        cpu.step(&mut bus).unwrap();
+        let err = cpu.step(&mut bus).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::IllegalInstr);
+        assert_eq!((err.op, err.pc), (Some(0xffff), 2));
+        // the cpu stays at the illegal instr
+        assert_eq!(cpu.regs.pc, 2);
+        assert_eq!(cpu.step(&mut bus).unwrap_err().pc, 2);
+    }
+
+    #[test]
+    fn strict_mode_address_error() {
+        let (mut cpu, mut bus) = setup(&[0x6211]);   // mov.w @r1, r2
+        cpu.regs.gpr[1] = 0x101;
+        cpu.set_strict(true);
+        let err = cpu.step(&mut bus).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::AddressError);
+        assert_eq!((err.addr, err.size), (Some(0x101), Some(2)));
+    }
+
+    #[test]
+    fn error_leaves_cpu_at_instr() {
+        let (mut cpu, mut bus) = setup(&[0xa002,      // bra 8
+                                         0x6211]);    // mov.w @r1, r2
+        cpu.regs.gpr[1] = 0x101;
+        cpu.set_strict(true);
+        cpu.step(&mut bus).unwrap();
+        let err = cpu.step(&mut bus).unwrap_err();
+        assert_eq!((err.pc, err.regs.pc), (2, 2));
+        assert!(cpu.delay);
+        // fixed up, the slot instr runs again and the branch is taken
+        cpu.regs.gpr[1] = 0x100;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 8);
+    }
+
+    #[test]
+    fn unmapped_access_error() {
+        let (mut cpu, mut bus) = setup(&[0x0009, 0x0009]);
+        cpu.step(&mut bus).unwrap();
+        bus.fault = Some(BusFault { addr: 0x1234, size: 4, write: true });
+        let err = cpu.step(&mut bus).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Unmapped);
+        assert_eq!((err.pc, err.addr, err.size), (2, Some(0x1234), Some(4)));
+        assert_eq!(err.regs.pc, 2);
+        assert!(format!("{}", err).starts_with("unmapped write at pc"));
+    }
+
+    #[test]
+    fn unmapped_access_retry() {
+        // mov.l r1, @-r2 and mov.l @r3+, r4
+        let (mut cpu, mut bus) = setup(&[0x2216, 0x6436]);
+        cpu.regs.gpr[1] = 0x12345678;
+        cpu.regs.gpr[2] = 0x1004;
+        cpu.regs.gpr[3] = 0x1000;
+        cpu.regs.gpr[4] = 0;
+        bus.fault = Some(BusFault { addr: 0x1000, size: 4, write: true });
+        let err = cpu.step(&mut bus).unwrap_err();
+        assert_eq!(err.addr, Some(0x1000));
+        assert_eq!((err.regs.pc, err.regs.gpr[2]), (0, 0x1004));
+        assert_eq!(cpu.regs.gpr[2], 0x1004);
+        // the retry writes to the address of the error
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.gpr[2], 0x1000);
+        assert_eq!(u32::read_mem(&bus.mem, 0x1000), 0x12345678);
+
+        bus.fault = Some(BusFault { addr: 0x1000, size: 4, write: false });
+        let err = cpu.step(&mut bus).unwrap_err();
+        assert_eq!((err.regs.gpr[3], err.regs.gpr[4]), (0x1000, 0));
+        assert_eq!((cpu.regs.gpr[3], cpu.regs.pc), (0x1000, 2));
+        cpu.step(&mut bus).unwrap();
+        assert_eq!((cpu.regs.gpr[3], cpu.regs.gpr[4]), (0x1004, 0x12345678));
+    }
+
+    #[test]
+    fn run_for_cycles() {
+        let (mut cpu, mut bus) = setup(&[0x0009; 0x10]);
+        cpu.run(&mut bus, 5).unwrap();
+        assert_eq!(cpu.cycles, 5);
+        assert_eq!(cpu.regs.pc, 10);
+    }
+
+    // interrupts
+
+    // nops, with the vbr at 0x100 and vector 0x40 pointing at 0x80
+    fn setup_irq() -> (Sh2, TestBus) {
+        let (mut cpu, mut bus) = setup(&[0x0009; 0x60]);
+        cpu.regs.vbr = 0x100;
+        u32::write_mem(&mut bus.mem, 0x100 + 0x40 * 4, 0x80);
+        (cpu, bus)
+    }
+
+    #[test]
+    fn irq_masked_by_sr_i() {
+        let (mut cpu, mut bus) = setup_irq();
+        cpu.regs.sr_i = 5;
+        cpu.assert_irq(5, 0x40);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 2);
+        cpu.assert_irq(6, 0x40);
+        cpu.step(&mut bus).unwrap();
+        // the handler's first instr is executed in the same step
+        assert_eq!(cpu.regs.pc, 0x82);
+        assert_eq!(cpu.regs.sr_i, 6);
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 8), 2);
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 4) & 0xf0, 0x50);
+    }
+
+    #[test]
+    fn irq_deasserted() {
+        let (mut cpu, mut bus) = setup_irq();
+        cpu.regs.sr_i = 0;
+        cpu.assert_irq(1, 0x40);
+        cpu.deassert_irq();
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 2);
+    }
+
+    #[test]
+    fn irq_not_after_sts_l_or_delayed_branch() {
+        let (mut cpu, mut bus) = setup_irq();
+        u16::write_mem(&mut bus.mem, 0, 0x4f22);     // sts.l pr, @-r15
+        u16::write_mem(&mut bus.mem, 4, 0xa000);     // bra 0x8
+        cpu.regs.sr_i = 0;
+        cpu.step(&mut bus).unwrap();
+        cpu.assert_irq(1, 0x40);
+        // not accepted before the nop after sts.l
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 4);
+        cpu.deassert_irq();
+        cpu.step(&mut bus).unwrap();
+        cpu.assert_irq(1, 0x40);
+        // not in between bra and its slot
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 8);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 0x82);
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 4 - 8), 8);
+    }
+
+    #[test]
+    fn irq_wakes_from_sleep() {
+        let (mut cpu, mut bus) = setup_irq();
+        u16::write_mem(&mut bus.mem, 0, 0x001b);     // sleep
+        cpu.regs.sr_i = 0;
+        run(&mut cpu, &mut bus, 4);
+        assert!(cpu.sleeping);
+        cpu.assert_irq(3, 0x40);
+        cpu.step(&mut bus).unwrap();
+        assert!(!cpu.sleeping);
+        assert_eq!(cpu.regs.pc, 0x82);
+        // returns to the instr after sleep
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 8), 2);
+    }
+
+    #[test]
+    fn nmi_ignores_sr_i() {
+        let (mut cpu, mut bus) = setup_irq();
+        u32::write_mem(&mut bus.mem, 0x100 + 11 * 4, 0x90);
+        cpu.regs.sr_i = 15;
+        cpu.assert_irq(15, 0x40);
+        cpu.nmi();
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 0x92);
+        assert_eq!(cpu.regs.sr_i, 15);
+        assert_eq!(u32::read_mem(&bus.mem, 0x10000 - 8), 0);
+        // an edge is only taken once
+        cpu.deassert_irq();
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 0x94);
+    }
+
+    #[test]
+    fn nmi_from_bus() {
+        let (mut cpu, mut bus) = setup_irq();
+        u32::write_mem(&mut bus.mem, 0x100 + 11 * 4, 0x90);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 2);
+        bus.nmi = true;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 0x92);
+    }
+
+    // resets
+
+    struct ResetBus {
+        mem: TestBus,
+        reset: Option<Reset>,
+        // a reset the bus raises itself
+        raise: Option<Reset>,
+    }
+
+    impl Bus for ResetBus {
+        fn read_byte(&self, addr: u32) -> u8 { self.mem.read_byte(addr) }
+        fn read_word(&self, addr: u32) -> u16 { self.mem.read_word(addr) }
+        fn read_long(&self, addr: u32) -> u32 { self.mem.read_long(addr) }
+        fn write_byte(&mut self, addr: u32, val: u8) {
+            self.mem.write_byte(addr, val)
+        }
+        fn write_word(&mut self, addr: u32, val: u16) {
+            self.mem.write_word(addr, val)
+        }
+        fn write_long(&mut self, addr: u32, val: u32) {
+            self.mem.write_long(addr, val)
+        }
+        fn reset(&mut self, kind: Reset) {
+            self.reset = Some(kind);
+        }
+        fn take_reset(&mut self) -> Option<Reset> {
+            self.raise.take()
+        }
+    }
+
+    fn reset_bus() -> ResetBus {
+        let mut mem = TestBus::with_prog(&[]);
+        u32::write_mem(&mut mem.mem, 0x0, 0x400);
+        u32::write_mem(&mut mem.mem, 0x4, 0x8000);
+        u32::write_mem(&mut mem.mem, 0x8, 0x600);
+        u32::write_mem(&mut mem.mem, 0xc, 0x9000);
+        ResetBus { mem, reset: None, raise: None }
+    }
+
+    #[test]
+    fn power_on_reset() {
+        let mut bus = reset_bus();
+        let mut cpu = Sh2::new();
+        cpu.regs.vbr = 0x100;
+        cpu.regs.sr_i = 0;
+        cpu.sleeping = true;
+        cpu.power_on_reset(&mut bus);
+        assert_eq!(bus.reset, Some(Reset::PowerOn));
+        assert_eq!(cpu.regs.pc, 0x400);
+        assert_eq!(cpu.regs.gpr[15], 0x8000);
+        assert_eq!(cpu.regs.vbr, 0);
+        assert_eq!(cpu.regs.sr_i, 0xf);
+        assert!(!cpu.sleeping);
+    }
+
+    #[test]
+    fn manual_reset() {
+        let mut bus = reset_bus();
+        let mut cpu = Sh2::new();
+        cpu.manual_reset(&mut bus);
+        assert_eq!(bus.reset, Some(Reset::Manual));
+        assert_eq!(cpu.regs.pc, 0x600);
+        assert_eq!(cpu.regs.gpr[15], 0x9000);
+    }
+
+    #[test]
+    fn reset_from_bus() {
+        let mut bus = reset_bus();
+        u16::write_mem(&mut bus.mem.mem, 0x600, 0x0009);
+        let mut cpu = Sh2::new();
+        cpu.reset(0x1000, 0x2000);
+        bus.raise = Some(Reset::Manual);
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(bus.reset, Some(Reset::Manual));
+        assert_eq!(cpu.regs.gpr[15], 0x9000);
+        assert_eq!(cpu.regs.pc, 0x602);
+    }
+
+    // pipeline stalls
+
+    #[test]
+    fn load_use_stall() {
+        let (mut cpu, mut bus) = setup(&[0x6212,     // mov.l @r1, r2
+                                         0x334c,     // add r4, r3
+                                         0x6212,     // mov.l @r1, r2
+                                         0x332c]);   // add r2, r3
+        cpu.regs.gpr[1] = 0x100;
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.cycles, 2);
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.cycles, 5);
+    }
+
+    #[test]
+    fn load_use_r0() {
+        // mov.l @(disp,gbr), r0 followed by cmp/eq #imm, r0
+        assert_eq!(load_dest(0xc601), Some(0));
+        assert!(reads_reg(0x8801, 0));
+        // mov r0, r1 only writes r1
+        assert!(!reads_reg(0x6103, 1));
+        assert!(reads_reg(0x6103, 0));
+        // mov.l r1, @(r0, r2) reads all three
+        assert!(reads_reg(0x0216, 0) && reads_reg(0x0216, 1) &&
+                reads_reg(0x0216, 2));
+        // mul.l doesn't use r0 unless asked
+        assert!(!reads_reg(0x0217, 0));
+    }
+
+    #[test]
+    fn multiplier_contention() {
+        let (mut cpu, mut bus) = setup(&[0x0217,     // mul.l r1, r2
+                                         0x001a,     // sts macl, r0
+                                         0x0217,     // mul.l r1, r2
+                                         0x0009,     // nop
+                                         0x0009,     // nop
+                                         0x001a]);   // sts macl, r0
+        run(&mut cpu, &mut bus, 2);
+        // sts waits 2 cycles for the result
+        assert_eq!(cpu.cycles, 2 + 2 + 1);
+        run(&mut cpu, &mut bus, 4);
+        assert_eq!(cpu.cycles, 5 + 2 + 1 + 1 + 1);
+    }
+
+    #[test]
+    fn back_to_back_multiplies() {
+        let (mut cpu, mut bus) = setup(&[0x221f,     // muls.w r1, r2
+                                         0x221f,     // muls.w r1, r2
+                                         0x0009]);   // nop
+        run(&mut cpu, &mut bus, 3);
+        // the second muls.w waits for the first one to get out of the
+        // multiplier
+        assert_eq!(cpu.cycles, 1 + 2 + 1 + 1);
+    }
+
+    #[test]
+    fn irq_from_bus() {
+        let (mut cpu, mut bus) = setup_irq();
+        u32::write_mem(&mut bus.mem, 0x100 + 0x41 * 4, 0x90);
+        cpu.regs.sr_i = 0;
+        cpu.assert_irq(3, 0x40);
+        bus.irq = Some((3, 0x41));
+        // the pins win at the same level
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 0x82);
+        cpu.regs.sr_i = 0;
+        bus.irq = Some((4, 0x41));
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 0x92);
+        assert_eq!(cpu.regs.sr_i, 4);
+    }
+
+    #[test]
+    fn irq_accept_cycles() {
+        let (mut cpu, mut bus) = setup_irq();
+        cpu.regs.sr_i = 0;
+        bus.irq = Some((3, 0x40));
+        // accepting takes 8, then the first instr of the handler
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.regs.pc, 0x82);
+        assert_eq!(cpu.cycles, 8 + 1);
+        assert_eq!(bus.ticks, 8 + 1);
+    }
+
+    #[test]
+    fn bus_ticks() {
+        let (mut cpu, mut bus) = setup(&[0x0009,      // nop
+                                         0x0217,      // mul.l r1, r2
+                                         0x001a,      // sts macl, r0
+                                         0x0009]);    // nop
+        run(&mut cpu, &mut bus, 3);
+        assert_eq!(bus.ticks, cpu.cycles);
+        assert_eq!(bus.ticks, 1 + 2 + 3);
+        bus.stall = 5;
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(cpu.cycles, 6 + 1 + 5);
+    }
 }
@@ -1,77 +1,468 @@
 // emulation for the SH7606 microcontroller non-cpu parts
 
-use bus::Bus;
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufReader, Read, Write};
+use std::mem;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
 
+use bus::{Bus, BusFault, Reset};
+
+// access widths in bytes, and as flags for the register map
+const B: u8 = 1;
+const W: u8 = 2;
+const L: u8 = 4;
+
+// reset value of registers the manual leaves undefined
+const UNDEF: u32 = 0xdeadbeef;
+
+// the on-chip modules, for resetting them separately
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Module {
+    Sci,
+    Frt,
+    Intc,
+    Dmac,
+    Wdt,
+    Power,
+    Cache,
+    Divu,
+    Ubc,
+    Bsc,
+}
+
+use self::Module::*;
+
+// an on-chip register as listed in the manual
+struct RegDef {
+    module: Module,
+    name: &'static str,
+    addr: u32,
+    // in bytes
+    size: u32,
+    // allowed access widths, as B, W and L flags. A register can be accessed
+    // in parts, for ex. a 16 bit one as two bytes when B is allowed.
+    read: u8,
+    write: u8,
+    reset: u32,
+}
+
+const fn reg(module: Module, name: &'static str, addr: u32, size: u32,
+             read: u8, write: u8, reset: u32) -> RegDef {
+    RegDef { module, name, addr, size, read, write, reset }
+}
+
+// registers the on-chip modules use
+const SMR: u32 = 0xfffffe00;
+const BRR: u32 = 0xfffffe01;
+const SCR: u32 = 0xfffffe02;
+const TDR: u32 = 0xfffffe03;
+const SSR: u32 = 0xfffffe04;
+const RDR: u32 = 0xfffffe05;
+const TIER: u32 = 0xfffffe10;
+const FTCSR: u32 = 0xfffffe11;
+const FRCH: u32 = 0xfffffe12;
+const FRCL: u32 = 0xfffffe13;
+const OCRH: u32 = 0xfffffe14;
+const OCRL: u32 = 0xfffffe15;
+const TCR: u32 = 0xfffffe16;
+const TOCR: u32 = 0xfffffe17;
+const FICRH: u32 = 0xfffffe18;
+const FICRL: u32 = 0xfffffe19;
+const IPRB: u32 = 0xfffffe60;
+const VCRA: u32 = 0xfffffe62;
+const VCRB: u32 = 0xfffffe64;
+const VCRC: u32 = 0xfffffe66;
+const VCRD: u32 = 0xfffffe68;
+const DRCR0: u32 = 0xfffffe71;
+const WTCSR: u32 = 0xfffffe80;
+const WTCNT: u32 = 0xfffffe81;
+const RSTCSR: u32 = 0xfffffe83;
+const SBYCR: u32 = 0xfffffe91;
+const ICR: u32 = 0xfffffee0;
+const IPRA: u32 = 0xfffffee2;
+const VCRWDT: u32 = 0xfffffee4;
+const DVSR: u32 = 0xffffff00;
+const DVDNT: u32 = 0xffffff04;
+const DVCR: u32 = 0xffffff08;
+const VCRDIV: u32 = 0xffffff0c;
+const DVDNTH: u32 = 0xffffff10;
+const DVDNTL: u32 = 0xffffff14;
+const SAR0: u32 = 0xffffff80;
+const TCR0: u32 = 0xffffff88;
+const TCR1: u32 = 0xffffff98;
+const CHCR0: u32 = 0xffffff8c;
+const CHCR1: u32 = 0xffffff9c;
+const VCRDMA0: u32 = 0xffffffa0;
+const VCRDMA1: u32 = 0xffffffa8;
+const DMAOR: u32 = 0xffffffb0;
+const CCR: u32 = 0xfffffe92;
+const BCR1: u32 = 0xffffffe0;
+const BCR2: u32 = 0xffffffe4;
+const WCR: u32 = 0xffffffe8;
+const RTCSR: u32 = 0xfffffff0;
+const RTCNT: u32 = 0xfffffff4;
+const RTCOR: u32 = 0xfffffff8;
+
+// sorted on address
+static REG_MAP: &[RegDef] = &[
+    //  module name         address     size read   write  reset
+
+    // SCI (Serial Communication Interface)
+    reg(Sci,   "SMR",       0xfffffe00, 1,   B,     B,     0x00),
+    reg(Sci,   "BRR",       0xfffffe01, 1,   B,     B,     0xff),
+    reg(Sci,   "SCR",       0xfffffe02, 1,   B,     B,     0x00),
+    reg(Sci,   "TDR",       0xfffffe03, 1,   B,     B,     0xff),
+    reg(Sci,   "SSR",       0xfffffe04, 1,   B,     B,     0x84),
+    reg(Sci,   "RDR",       0xfffffe05, 1,   B,     0,     0x00),
+
+    // FRT (Free Running Timer). OCRB lives at the same address as OCRA,
+    // TOCR selects which one is accessed.
+    reg(Frt,   "TIER",      0xfffffe10, 1,   B,     B,     0x01),
+    reg(Frt,   "FTCSR",     0xfffffe11, 1,   B,     B,     0x00),
+    reg(Frt,   "FRCH",      0xfffffe12, 1,   B,     B,     0x00),
+    reg(Frt,   "FRCL",      0xfffffe13, 1,   B,     B,     0x00),
+    reg(Frt,   "OCRH",      0xfffffe14, 1,   B,     B,     0xff),
+    reg(Frt,   "OCRL",      0xfffffe15, 1,   B,     B,     0xff),
+    reg(Frt,   "TCR",       0xfffffe16, 1,   B,     B,     0x00),
+    reg(Frt,   "TOCR",      0xfffffe17, 1,   B,     B,     0xe0),
+    reg(Frt,   "FICRH",     0xfffffe18, 1,   B,     0,     0x00),
+    reg(Frt,   "FICRL",     0xfffffe19, 1,   B,     0,     0x00),
+
+    // INTC (Interrupt Controller)
+    reg(Intc,  "IPRB",      0xfffffe60, 2,   B | W, B | W, 0x0000),
+    reg(Intc,  "VCRA",      0xfffffe62, 2,   B | W, B | W, 0x0000),
+    reg(Intc,  "VCRB",      0xfffffe64, 2,   B | W, B | W, 0x0000),
+    reg(Intc,  "VCRC",      0xfffffe66, 2,   B | W, B | W, 0x0000),
+    reg(Intc,  "VCRD",      0xfffffe68, 2,   B | W, B | W, 0x0000),
+
+    // DMAC request/response selection
+    reg(Dmac,  "DRCR0",     0xfffffe71, 1,   B,     B,     0x00),
+    reg(Dmac,  "DRCR1",     0xfffffe72, 1,   B,     B,     0x00),
+
+    // WDT (Watchdog Timer). Written with a password in a word write to
+    // 0xfffffe80 or 0xfffffe82.
+    reg(Wdt,   "WTCSR",     0xfffffe80, 1,   B,     0,     0x18),
+    reg(Wdt,   "WTCNT",     0xfffffe81, 1,   B,     0,     0x00),
+    reg(Wdt,   "RSTCSR",    0xfffffe83, 1,   B,     0,     0x1f),
+
+    // power-down modes and cache
+    reg(Power, "SBYCR",     0xfffffe91, 1,   B,     B,     0x00),
+    reg(Cache, "CCR",       0xfffffe92, 1,   B,     B,     0x00),
+
+    // INTC
+    reg(Intc,  "ICR",       0xfffffee0, 2,   B | W, B | W, 0x0000),
+    reg(Intc,  "IPRA",      0xfffffee2, 2,   B | W, B | W, 0x0000),
+    reg(Intc,  "VCRWDT",    0xfffffee4, 2,   B | W, B | W, 0x0000),
+
+    // DIVU (Division Unit)
+    reg(Divu,  "DVSR",      0xffffff00, 4,   L,     L,     UNDEF),
+    reg(Divu,  "DVDNT",     0xffffff04, 4,   L,     L,     UNDEF),
+    reg(Divu,  "DVCR",      0xffffff08, 4,   W | L, W | L, 0x00000000),
+    reg(Divu,  "VCRDIV",    0xffffff0c, 4,   L,     L,     UNDEF),
+    reg(Divu,  "DVDNTH",    0xffffff10, 4,   L,     L,     UNDEF),
+    reg(Divu,  "DVDNTL",    0xffffff14, 4,   L,     L,     UNDEF),
+
+    // UBC (User Break Controller)
+    reg(Ubc,   "BARA",      0xffffff40, 4,   W | L, W | L, 0x00000000),
+    reg(Ubc,   "BAMRA",     0xffffff44, 4,   W | L, W | L, 0x00000000),
+    reg(Ubc,   "BBRA",      0xffffff48, 2,   W,     W,     0x0000),
+    reg(Ubc,   "BARB",      0xffffff60, 4,   W | L, W | L, 0x00000000),
+    reg(Ubc,   "BAMRB",     0xffffff64, 4,   W | L, W | L, 0x00000000),
+    reg(Ubc,   "BBRB",      0xffffff68, 2,   W,     W,     0x0000),
+    reg(Ubc,   "BDRB",      0xffffff70, 4,   W | L, W | L, 0x00000000),
+    reg(Ubc,   "BDMRB",     0xffffff74, 4,   W | L, W | L, 0x00000000),
+    reg(Ubc,   "BRCR",      0xffffff78, 2,   W,     W,     0x0000),
+
+    // DMAC (Direct Memory Access Controller)
+    reg(Dmac,  "SAR0",      0xffffff80, 4,   L,     L,     UNDEF),
+    reg(Dmac,  "DAR0",      0xffffff84, 4,   L,     L,     UNDEF),
+    reg(Dmac,  "TCR0",      0xffffff88, 4,   L,     L,     UNDEF),
+    reg(Dmac,  "CHCR0",     0xffffff8c, 4,   L,     L,     0x00000000),
+    reg(Dmac,  "SAR1",      0xffffff90, 4,   L,     L,     UNDEF),
+    reg(Dmac,  "DAR1",      0xffffff94, 4,   L,     L,     UNDEF),
+    reg(Dmac,  "TCR1",      0xffffff98, 4,   L,     L,     UNDEF),
+    reg(Dmac,  "CHCR1",     0xffffff9c, 4,   L,     L,     0x00000000),
+    reg(Dmac,  "VCRDMA0",   0xffffffa0, 4,   L,     L,     UNDEF),
+    reg(Dmac,  "VCRDMA1",   0xffffffa8, 4,   L,     L,     UNDEF),
+    reg(Dmac,  "DMAOR",     0xffffffb0, 4,   L,     L,     0x00000000),
+
+    // BSC (Bus State Controller). The upper word reads as 0, and has to
+    // hold 0xa55a on a write for it to take effect.
+    reg(Bsc,   "BCR1",      0xffffffe0, 4,   W | L, L,     0x000003f0),
+    reg(Bsc,   "BCR2",      0xffffffe4, 4,   W | L, L,     0x000000fc),
+    reg(Bsc,   "WCR",       0xffffffe8, 4,   W | L, L,     0x0000aaff),
+    reg(Bsc,   "MCR",       0xffffffec, 4,   W | L, L,     0x00000000),
+    reg(Bsc,   "RTCSR",     0xfffffff0, 4,   W | L, L,     0x00000000),
+    reg(Bsc,   "RTCNT",     0xfffffff4, 4,   W | L, L,     0x00000000),
+    reg(Bsc,   "RTCOR",     0xfffffff8, 4,   W | L, L,     0x00000000),
+];
+
+// the register at addr and the offset into it, taking mirrors into account
+fn find_reg(addr: u32) -> Option<(&'static RegDef, u32)> {
+    let addr = match addr {
+        // the DIVU regs repeat at 0xffffff20, and DVDNTH/L at 0xffffff18
+        0xffffff20 ..= 0xffffff3f => addr - 0x20,
+        _ => addr
+    };
+    let addr = match addr {
+        0xffffff18 ..= 0xffffff1f => addr - 0x8,
+        _ => addr
+    };
+    REG_MAP.iter()
+        .find(|reg| addr >= reg.addr && addr < reg.addr + reg.size)
+        .map(|reg| (reg, addr - reg.addr))
+}
+
+fn width_mask(width: u32) -> u32 {
+    match width {
+        1 => 0xff,
+        2 => 0xffff,
+        _ => 0xffffffff,
+    }
+}
+
+// SBYCR module stop bits, and the module each one stops. MSTP3 stops the
+// multiplier, which lives in the cpu.
+const SBYCR_MSTP: [(u8, Module); 4] = [
+    (0x01, Sci),
+    (0x02, Frt),
+    (0x04, Divu),
+    (0x10, Dmac),
+];
+
+// TIER and FTCSR bits. The interrupt enables in TIER line up with the
+// flags in FTCSR.
+const FTCSR_ICF: u8 = 0x80;   // input capture
+const FTCSR_OCFA: u8 = 0x08;  // compare match A
+const FTCSR_OCFB: u8 = 0x04;  // compare match B
+const FTCSR_OVF: u8 = 0x02;   // FRC overflow
+const FTCSR_CCLRA: u8 = 0x01; // clear FRC on compare match A
+const FTCSR_FLAGS: u8 = FTCSR_ICF | FTCSR_OCFA | FTCSR_OCFB | FTCSR_OVF;
+
+// TOCR bits
+const TOCR_OCRS: u32 = 0x10; // access OCRB instead of OCRA
+const TOCR_OLVLA: u8 = 0x02; // FTOA level on compare match A
+const TOCR_OLVLB: u8 = 0x01; // FTOB level on compare match B
+
+// TCR bits
+const TCR_IEDGA: u8 = 0x80; // input capture on rising (1) or falling (0) edge
+
+// interrupt flag and enable bits of the modules, as seen by the INTC
+const SMR_CA: u8 = 0x80;      // clocked synchronous (1) or async (0)
+const SMR_CHR: u8 = 0x40;     // 7 (1) or 8 (0) data bits
+const SMR_PE: u8 = 0x20;      // parity bit
+const SMR_STOP: u8 = 0x08;    // 2 (1) or 1 (0) stop bits
+const SCR_TIE: u8 = 0x80;
+const SCR_RIE: u8 = 0x40;
+const SCR_TE: u8 = 0x20;
+const SCR_RE: u8 = 0x10;
+const SCR_TEIE: u8 = 0x04;
+const SSR_TDRE: u8 = 0x80;
+const SSR_RDRF: u8 = 0x40;
+const SSR_ORER: u8 = 0x20;
+const SSR_ERRORS: u8 = 0x38;  // ORER, FER and PER
+const SSR_TEND: u8 = 0x04;
+const SSR_MPB: u8 = 0x02;
+const SSR_MPBT: u8 = 0x01;
+const WTCSR_OVF: u8 = 0x80;
+const WTCSR_WT: u8 = 0x40;    // watchdog (1) or interval timer (0) mode
+const WTCSR_TME: u8 = 0x20;   // timer enable
+const RSTCSR_WOVF: u8 = 0x80; // watchdog overflow
+const RSTCSR_RSTE: u8 = 0x40; // reset on watchdog overflow
+const RSTCSR_RSTS: u8 = 0x20; // manual (1) or power-on (0) reset
+const DVCR_OVF: u32 = 0x01;
+const DVCR_OVFIE: u32 = 0x02;
+// cycles a division takes, 32/32 or 64/32
+const DIVU_LATENCY: u64 = 39;
+const CHCR_TE: u32 = 0x02;
+const CHCR_IE: u32 = 0x04;
+
+// the rest of CHCR
+const CHCR_DE: u32 = 0x001;    // channel enable
+const CHCR_TA: u32 = 0x008;    // single (1) or dual (0) address mode
+const CHCR_TB: u32 = 0x010;    // burst (1) or cycle steal (0) mode
+const CHCR_DL: u32 = 0x020;    // DREQ active high or on the rising edge
+const CHCR_DS: u32 = 0x040;    // DREQ edge (1) or level (0) detection
+const CHCR_AM: u32 = 0x100;    // single address: device to memory (1)
+const CHCR_AR: u32 = 0x200;    // auto request
+const CHCR_MASK: u32 = 0xffff;
+
+// DMAOR bits
+const DMAOR_DME: u32 = 0x1;    // DMA master enable
+const DMAOR_NMIF: u32 = 0x2;   // stopped by an NMI
+const DMAOR_AE: u32 = 0x4;     // stopped by an address error
+const DMAOR_PR: u32 = 0x8;     // round robin (1) or fixed (0) priority
+const RTCSR_CMF: u32 = 0x80;
+const RTCSR_CMIE: u32 = 0x40;
+const RTCSR_CKS: u32 = 0x38;   // refresh counter clock select
+const CCR_CE: u8 = 0x01;       // cache enable
+
+// ICR bits
+const ICR_NMIL: u16 = 0x8000;  // NMI pin level, read only
+const ICR_NMIE: u16 = 0x0100;  // NMI on rising (1) or falling (0) edge
+const ICR_VECMD: u16 = 0x0001; // IRL interrupt vector mode
+const ICR_WRITE_MASK: u16 = ICR_NMIE | ICR_VECMD;
+
+// Storage for the on-chip register area 0xfffffe00-0xffffffff, big endian
+// like the cpu sees it. The register map decides what can be accessed how.
 struct Regs {
-    //                           access
-    //                             size
-
-    // FRT (Free Running Timer) regs
-    tier:     u8,  // 0xFFFFFE10      8
-    ftcsr:     u8, // 0xFFFFFE11      8
-    _frc_h:    u8, // 0xFFFFFE12      8
-    _frc_l:    u8, // 0xFFFFFE13      8
-    ocra_h:    u8, // 0xFFFFFE14      8
-    ocra_l:    u8, // 0xFFFFFE15      8
-    ocrb_h:    u8, // 0xFFFFFE14      8
-    ocrb_l:    u8, // 0xFFFFFE15      8
-    tcr:       u8, // 0xFFFFFE16      8
-    tocr:      u8, // 0xFFFFFE17      8
-    _icr_h:    u8, // 0xFFFFFE18      8
-    _icr_l:    u8, // 0xFFFFFE19      8
-
-    // INTC io regs
-    _ipra:    u16, // 0xFFFFFEE2  8, 16
-    iprb:     u16, // 0xFFFFFE60  8, 16
-    _vcra:    u16, // 0xFFFFFE62  8, 16
-    _vcrb:    u16, // 0xFFFFFE64  8, 16
-    vcrc:     u16, // 0xFFFFFE66  8, 16
-    _vcrd:    u16, // 0xFFFFFE68  8, 16
-    _vcrwdt:  u16, // 0xFFFFFEE4  8, 16
-    _vcrdiv:  u32, // 0xFFFFFF0C     32
-    _vcrdma0: u32, // 0xFFFFFFA0     32
-    _vcrdma1: u32, // 0xFFFFFFA8     32
-    _icr:     u16, // 0xFFFFFEE0  8, 16
+    mem: Vec<u8>,
+    // hidden behind OCRA
+    ocrb: u16,
+    // The FRT's 16 bit regs are accessed a byte at a time through TEMP:
+    // reading the high byte latches the low byte, and writing the high byte
+    // holds it until the low byte is written. Reads latch, hence the Cell.
+    temp: Cell<u8>,
 }
 
 impl Regs {
     fn new() -> Regs {
-        Regs {
-            // FRT
-            tier:           0x01,
-            ftcsr:          0x00,
-            _frc_h:         0x00,
-            _frc_l:         0x00,
-            ocra_h:         0xff,
-            ocra_l:         0xff,
-            ocrb_h:         0xff,
-            ocrb_l:         0xff,
-            tcr:            0x00,
-            tocr:           0xe0,
-            _icr_h:         0x00,
-            _icr_l:         0x00,
-
-            // INTC
-            _ipra:        0x0000,
-            iprb:         0x0000,
-            _vcra:        0x0000,
-            _vcrb:        0x0000,
-            vcrc:         0x0000,
-            _vcrd:        0x0000,
-            _vcrwdt:      0x0000,
-            _vcrdiv:  0xdeadbeef,
-            _vcrdma0: 0xdeadbeef,
-            _vcrdma1: 0xdeadbeef,
-            _icr:         0x0000,
-        }
-    }
-
-    // TODO: reset fn
+        let mut regs = Regs {
+            mem: vec![0; 0x200],
+            ocrb: 0xffff,
+            temp: Cell::new(0),
+        };
+        regs.reset(Reset::PowerOn);
+        regs
+    }
+
+    // A manual reset leaves the BSC alone, so external memory keeps working.
+    // RSTCSR is only initialized on a power-on reset, so software can tell
+    // whether a reset was caused by the watchdog.
+    fn reset(&mut self, kind: Reset) {
+        for reg in REG_MAP {
+            let keep = kind == Reset::Manual &&
+                       (reg.module == Bsc || reg.addr == RSTCSR);
+            if !keep {
+                self.set(reg.addr, reg.size, reg.reset);
+            }
+        }
+        self.ocrb = 0xffff;
+    }
+
+    fn reset_module(&mut self, module: Module) {
+        for reg in REG_MAP.iter().filter(|reg| reg.module == module) {
+            self.set(reg.addr, reg.size, reg.reset);
+        }
+        if module == Frt {
+            self.ocrb = 0xffff;
+        }
+    }
+
+    // a module in module standby has its clock stopped
+    fn stopped(&self, module: Module) -> bool {
+        let sbycr = self.get8(SBYCR);
+        SBYCR_MSTP.iter().any(|&(bit, m)| m == module && sbycr & bit != 0)
+    }
+
+    fn get(&self, addr: u32, size: u32) -> u32 {
+        let start = (addr - 0xfffffe00) as usize;
+        self.mem[start..start + size as usize].iter()
+            .fold(0, |val, byte| val << 8 | *byte as u32)
+    }
+
+    fn set(&mut self, addr: u32, size: u32, val: u32) {
+        let start = (addr - 0xfffffe00) as usize;
+        for i in 0..size as usize {
+            let shift = (size as usize - 1 - i) * 8;
+            self.mem[start + i] = (val >> shift) as u8;
+        }
+    }
+
+    fn get8(&self, addr: u32) -> u8 {
+        self.get(addr, 1) as u8
+    }
+
+    fn set8(&mut self, addr: u32, val: u8) {
+        self.set(addr, 1, val as u32);
+    }
+
+    fn get16(&self, addr: u32) -> u16 {
+        self.get(addr, 2) as u16
+    }
+
+    fn set16(&mut self, addr: u32, val: u16) {
+        self.set(addr, 2, val as u32);
+    }
+}
+
+// the FRT output compare pins
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrtPin {
+    Ftoa,
+    Ftob,
+}
+
+// What to do on an access to an on-chip address that isn't mapped (yet).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnmappedPolicy {
+    // stop right there
+    Panic,
+    // hand it to the cpu, which returns it from step as an Error
+    Fault,
+    // print it to stderr and carry on, reads return 0
+    Log,
+    // keep it in a list to inspect later, reads return 0
+    Record,
 }
 
 pub struct Sh7604Mem<U: Bus> {
     regs: Regs,
     pub user: U,
+    // level of the NMI input pin, high when idle
+    nmi_pin: bool,
+    // an NMI edge was detected that the cpu hasn't picked up yet
+    nmi_pending: bool,
+    // level on the IRL pins, 0 when none, and the vector the external
+    // device hands out in external vector mode
+    irl: u32,
+    irl_vector: u32,
+    // DREQ pin levels, and the edges seen on them that haven't been served
+    dreq: [bool; 2],
+    dreq_edge: [bool; 2],
+    // the DMA channel that transferred last, for round robin priority
+    dma_last: usize,
+    // a DMA address error the cpu hasn't picked up yet
+    dma_addr_error: bool,
+    // cycles the cpu was held up by the DMAC or DIVU, handed out through
+    // Bus::take_stall
+    stall: u64,
+    // width in bytes of the memory in area 0, set by the MD3-4 pins
+    area0_width: u32,
+    // cycles towards the next RTCNT count
+    rtc_prescale: u64,
+    // cycles until the running division is done, and what the access that
+    // started it has to wait for the one before
+    divu_busy: u64,
+    divu_owed: Option<u64>,
+    // the host end of the serial line: a stream the bytes sent go to, and
+    // the bytes received from a stream, which is read on its own thread
+    sci_out: Option<Box<dyn Write + Send>>,
+    sci_in: Option<Receiver<u8>>,
+    // the frames being sent and received, and the cycles until they're done
+    sci_tx: Option<(u8, u64)>,
+    sci_rx: Option<(u8, u64)>,
+    // cycles towards the next WTCNT count
+    wdt_prescale: u64,
+    // a reset the watchdog raised that the cpu hasn't picked up yet, and
+    // whether the reset the cpu is going through is one of those
+    wdt_reset: Option<Reset>,
+    wdt_resetting: bool,
+    // cycles towards the next FRC count
+    frt_prescale: u64,
+    // level of the FTI input capture pin
+    fti_pin: bool,
+    // levels of the FTOA and FTOB output pins, and the changes to them the
+    // host hasn't seen yet
+    fto: [bool; 2],
+    fto_changes: Vec<(FrtPin, bool)>,
+    unmapped_policy: UnmappedPolicy,
+    // access to an on-chip address we don't handle. Reads only get &self,
+    // hence the Cell.
+    fault: Cell<Option<BusFault>>,
+    // unmapped accesses seen with UnmappedPolicy::Record
+    unmapped_list: RefCell<Vec<BusFault>>,
 }
 
 impl<U: Bus> Sh7604Mem<U> {
@@ -79,10 +470,906 @@ impl<U: Bus> Sh7604Mem<U> {
         Sh7604Mem {
             regs: Regs::new(),
             user: user_mem,
+            nmi_pin: true,
+            nmi_pending: false,
+            irl: 0,
+            irl_vector: 0,
+            dreq: [false; 2],
+            dreq_edge: [false; 2],
+            dma_last: 1,
+            dma_addr_error: false,
+            stall: 0,
+            area0_width: 4,
+            rtc_prescale: 0,
+            divu_busy: 0,
+            divu_owed: None,
+            sci_out: None,
+            sci_in: None,
+            sci_tx: None,
+            sci_rx: None,
+            wdt_prescale: 0,
+            wdt_reset: None,
+            wdt_resetting: false,
+            frt_prescale: 0,
+            fti_pin: false,
+            fto: [false; 2],
+            fto_changes: Vec::new(),
+            unmapped_policy: UnmappedPolicy::Fault,
+            fault: Cell::new(None),
+            unmapped_list: RefCell::new(Vec::new()),
+        }
+    }
+
+    // Drive the NMI input pin. An NMI is requested on the edge selected by
+    // ICR NMIE, and passed on to the cpu through Bus::take_nmi.
+    pub fn set_nmi(&mut self, level: bool) {
+        let rising = self.regs.get16(ICR) & ICR_NMIE != 0;
+        if level != self.nmi_pin && level == rising {
+            self.nmi_pending = true;
+            // an NMI stops the DMAC
+            let dmaor = self.regs.get(DMAOR, 4);
+            self.regs.set(DMAOR, 4, dmaor | DMAOR_NMIF);
+        }
+        self.nmi_pin = level;
+    }
+
+    pub fn set_unmapped_policy(&mut self, policy: UnmappedPolicy) {
+        self.unmapped_policy = policy;
+    }
+
+    // the unmapped accesses recorded so far, oldest first. The list is
+    // emptied.
+    pub fn take_unmapped(&mut self) -> Vec<BusFault> {
+        self.unmapped_list.replace(Vec::new())
+    }
+
+    // deal with an access to an unmapped on-chip address according to the
+    // policy. Reads return 0.
+    fn unmapped(&self, addr: u32, size: u32, write: bool) {
+        let fault = BusFault { addr, size, write };
+        let access = if write { "write" } else { "read" };
+        match self.unmapped_policy {
+            UnmappedPolicy::Panic =>
+                panic!("sh7604 {} byte {}: {:#010x} not (yet) mapped",
+                       size, access, addr),
+            UnmappedPolicy::Fault => self.fault.set(Some(fault)),
+            UnmappedPolicy::Log => match find_reg(addr) {
+                Some((reg, _)) =>
+                    eprintln!("sh7604 {} byte {}: {:#010x} not allowed on {}",
+                              size, access, addr, reg.name),
+                None =>
+                    eprintln!("sh7604 {} byte {}: {:#010x} not (yet) mapped",
+                              size, access, addr),
+            },
+            UnmappedPolicy::Record =>
+                self.unmapped_list.borrow_mut().push(fault),
+        }
+    }
+
+    // The register an access goes to, and the bit position of the accessed
+    // part in it. The access has to be aligned, of a width the register
+    // allows, and fall within the register.
+    fn find_access(&self, addr: u32, width: u32, write: bool)
+                   -> Option<(&'static RegDef, u32)> {
+        let (reg, offset) = find_reg(addr)?;
+        let allowed = if write { reg.write } else { reg.read };
+        if allowed & width as u8 != 0 && addr & (width - 1) == 0 &&
+           offset + width <= reg.size {
+            Some((reg, (reg.size - offset - width) * 8))
+        } else {
+            None
+        }
+    }
+
+    // the whole register, as the cpu sees it
+    fn read_reg(&self, reg: &RegDef) -> u32 {
+        let ocrs = self.regs.get(TOCR, 1) & TOCR_OCRS != 0;
+        match reg.addr {
+            FRCH | FICRH => {
+                self.regs.temp.set(self.regs.get8(reg.addr + 1));
+                self.regs.get(reg.addr, 1)
+            },
+            FRCL | FICRL => self.regs.temp.get() as u32,
+            OCRH if ocrs => (self.regs.ocrb >> 8) as u32,
+            OCRL if ocrs => (self.regs.ocrb & 0xff) as u32,
+            ICR => {
+                let nmil = if self.nmi_pin { ICR_NMIL } else { 0 };
+                (nmil | self.regs.get16(ICR)) as u32
+            },
+            _ => self.regs.get(reg.addr, reg.size)
+        }
+    }
+
+    fn write_reg(&mut self, reg: &RegDef, val: u32) {
+        let ocrs = self.regs.get(TOCR, 1) & TOCR_OCRS != 0;
+        match reg.addr {
+            FRCH | OCRH => self.regs.temp.set(val as u8),
+            FRCL => {
+                let frc = (self.regs.temp.get() as u16) << 8 | val as u16;
+                self.regs.set16(FRCH, frc);
+            },
+            OCRL => {
+                let ocr = (self.regs.temp.get() as u16) << 8 | val as u16;
+                if ocrs { self.regs.ocrb = ocr; }
+                else { self.regs.set16(OCRH, ocr); }
+            },
+            // TEND and MPB are read only, and the other flags can only be
+            // cleared. Clearing TDRE starts a transmission, so it also
+            // clears TEND.
+            SSR => {
+                let ssr = self.regs.get8(SSR);
+                let val = val as u8;
+                let mut new = ssr & val & 0xf8 | ssr & (SSR_TEND | SSR_MPB) |
+                              val & SSR_MPBT;
+                if new & SSR_TDRE == 0 {
+                    new &= !SSR_TEND;
+                }
+                self.regs.set8(SSR, new);
+            },
+            // disabling the transmitter or receiver drops the frame it's on
+            SCR => {
+                self.regs.set8(SCR, val as u8);
+                if val as u8 & SCR_TE == 0 {
+                    self.sci_tx = None;
+                    let ssr = self.regs.get8(SSR);
+                    self.regs.set8(SSR, ssr | SSR_TDRE | SSR_TEND);
+                }
+                if val as u8 & SCR_RE == 0 {
+                    self.sci_rx = None;
+                }
+            },
+            TIER => self.regs.set8(TIER, val as u8 | 0x01),
+            // the flags can only be cleared
+            FTCSR => {
+                let ftcsr = self.regs.get8(FTCSR);
+                let val = val as u8;
+                self.regs.set8(FTCSR, ftcsr & val & FTCSR_FLAGS |
+                                      val & FTCSR_CCLRA);
+            },
+            TOCR => self.regs.set8(TOCR, val as u8 | 0xe0),
+            TCR0 | TCR1 => self.regs.set(reg.addr, 4, val & 0xffffff),
+            // TE, AE and NMIF can only be cleared
+            CHCR0 | CHCR1 => {
+                let te = self.regs.get(reg.addr, 4) & val & CHCR_TE;
+                self.regs.set(reg.addr, 4, val & CHCR_MASK & !CHCR_TE | te);
+            },
+            DMAOR => {
+                let flags = self.regs.get(DMAOR, 4) & val &
+                            (DMAOR_AE | DMAOR_NMIF);
+                self.regs.set(DMAOR, 4, val & (DMAOR_PR | DMAOR_DME) | flags);
+            },
+            // a 32 bit dividend is sign extended into DVDNTH
+            DVDNT => {
+                self.regs.set(DVDNTH, 4, (val as i32 >> 31) as u32);
+                self.regs.set(DVDNTL, 4, val);
+                self.divide();
+            },
+            DVDNTL => {
+                self.regs.set(DVDNTL, 4, val);
+                self.divide();
+            },
+            ICR => self.regs.set16(ICR, val as u16 & ICR_WRITE_MASK),
+            // The FRT and SCI are initialized when they enter module
+            // standby, the DIVU and DMAC keep their register contents.
+            SBYCR => {
+                self.regs.set8(SBYCR, val as u8);
+                for &module in &[Sci, Frt] {
+                    if self.regs.stopped(module) {
+                        self.regs.reset_module(module);
+                    }
+                }
+            },
+            // CMF can only be cleared, RTCNT and RTCOR are 8 bits
+            BCR1 ..= RTCOR if val >> 16 == 0xa55a => {
+                let val = match reg.addr {
+                    RTCSR => {
+                        let cmf = self.regs.get(RTCSR, 4) & val & RTCSR_CMF;
+                        cmf | val & (RTCSR_CMIE | RTCSR_CKS)
+                    },
+                    RTCNT | RTCOR => val & 0xff,
+                    _ => val & 0xffff
+                };
+                self.regs.set(reg.addr, 4, val);
+            },
+            BCR1 ..= RTCOR => {},
+            _ => self.regs.set(reg.addr, reg.size, val)
+        }
+    }
+
+    // BSC
+
+    // Area 0 takes its bus width from the MD3 and MD4 pins rather than from
+    // BCR2. 1, 2 or 4 bytes.
+    pub fn set_area0_width(&mut self, width: u32) {
+        self.area0_width = width;
+    }
+
+    // The cycles an external access takes, on top of the one the access is
+    // already counted for. The cache isn't emulated, so with it enabled all
+    // accesses through the cached space are taken to hit.
+    //
+    // The SH7604 has four external areas, selected by A26-A25. An access
+    // wider than the bus of its area takes a bus cycle for every part.
+    // Ordinary memory takes 2 cycles plus the WCR wait states, SDRAM and
+    // DRAM take 3 plus the CAS latency or wait states from WCR.
+    fn bus_cycles(&self, addr: u32, size: u32) -> u64 {
+        match addr >> 29 {
+            0 if self.regs.get8(CCR) & CCR_CE != 0 => return 0,
+            0 | 1 => {},
+            _ => return 0
+        }
+        let area = addr >> 25 & 0x3;
+        let bcr1 = self.regs.get(BCR1, 4);
+
+        // BCR2 AnSZ
+        let width = match area {
+            0 => self.area0_width,
+            _ => match self.regs.get(BCR2, 4) >> (2 * area) & 0x3 {
+                1 => 1,
+                2 => 2,
+                _ => 4,
+            }
+        };
+
+        // BCR1 DRAM selects SDRAM or DRAM for areas 2-3
+        let wait = (self.regs.get(WCR, 4) >> (2 * area) & 0x3) as u64;
+        let cycles = match (area, bcr1 & 0x7) {
+            (3, 0b010) | (2, 0b110) | (3, 0b110) => 3 + wait.max(1),
+            (3, 0b011) => 3 + wait,
+            _ => {
+                // a wait of 3 is a long wait, of 3-6 cycles from A0LW,
+                // A1LW or AHLW
+                let long_shift = match area {
+                    0 => 4,
+                    1 => 6,
+                    _ => 8,
+                };
+                let wait = if wait == 3 {
+                    3 + (bcr1 >> long_shift & 0x3) as u64
+                } else {
+                    wait
+                };
+                2 + wait
+            }
+        };
+
+        let parts = (size / width).max(1) as u64;
+        parts * cycles - 1
+    }
+
+    // The refresh counter raises its compare match interrupt every RTCOR
+    // counts, whether or not there is DRAM to refresh.
+    fn rtc_tick(&mut self, cycles: u64) {
+        let rtcsr = self.regs.get(RTCSR, 4);
+        let div = match (rtcsr & RTCSR_CKS) >> 3 {
+            0 => return,
+            1 => 4,
+            2 => 16,
+            3 => 64,
+            4 => 256,
+            5 => 1024,
+            6 => 2048,
+            _ => 4096,
+        };
+        self.rtc_prescale += cycles;
+        while self.rtc_prescale >= div {
+            self.rtc_prescale -= div;
+            let rtcnt = (self.regs.get(RTCNT, 4) + 1) & 0xff;
+            if rtcnt == self.regs.get(RTCOR, 4) {
+                self.regs.set(RTCNT, 4, 0);
+                let rtcsr = self.regs.get(RTCSR, 4);
+                self.regs.set(RTCSR, 4, rtcsr | RTCSR_CMF);
+            } else {
+                self.regs.set(RTCNT, 4, rtcnt);
+            }
+        }
+    }
+
+    // INTC
+
+    // Drive the IRL3-0 pins with an interrupt level of 1-15, or 0 for no
+    // request. In external vector mode (ICR VECMD) the vector is the one
+    // the external device puts on the bus when the request is accepted,
+    // in auto-vector mode it is ignored.
+    pub fn set_irl(&mut self, level: u32, vector: u32) {
+        self.irl = level & 0xf;
+        self.irl_vector = vector & 0xff;
+    }
+
+    // all interrupt sources as (requesting, level, vector), in the order of
+    // priority they have among sources of the same level
+    fn irq_sources(&self) -> [(bool, u32, u32); 13] {
+        let r = &self.regs;
+        let ipra = r.get16(IPRA) as u32;
+        let iprb = r.get16(IPRB) as u32;
+        let vcr_hi = |addr| (r.get16(addr) >> 8) as u32 & 0x7f;
+        let vcr_lo = |addr| r.get16(addr) as u32 & 0x7f;
+
+        // IRL, auto-vectored to 64 + level / 2
+        let irl_vector = if r.get16(ICR) & ICR_VECMD != 0 { self.irl_vector }
+                         else { 64 + self.irl / 2 };
+
+        let dvcr = r.get(DVCR, 4);
+        let chcr0 = r.get(CHCR0, 4);
+        let chcr1 = r.get(CHCR1, 4);
+        let dma_irq = |chcr| chcr & (CHCR_TE | CHCR_IE) == CHCR_TE | CHCR_IE;
+        let wtcsr = r.get8(WTCSR);
+        let rtcsr = r.get(RTCSR, 4);
+        let scr = r.get8(SCR);
+        let ssr = r.get8(SSR);
+        let ftcsr = r.get8(FTCSR) & r.get8(TIER);
+
+        [
+            (self.irl != 0, self.irl, irl_vector),
+            (dvcr & (DVCR_OVF | DVCR_OVFIE) == DVCR_OVF | DVCR_OVFIE,
+             ipra >> 12 & 0xf, r.get(VCRDIV, 4) & 0x7f),
+            (dma_irq(chcr0), ipra >> 8 & 0xf, r.get(VCRDMA0, 4) & 0xff),
+            (dma_irq(chcr1), ipra >> 8 & 0xf, r.get(VCRDMA1, 4) & 0xff),
+            // interval timer
+            (wtcsr & (WTCSR_OVF | WTCSR_WT) == WTCSR_OVF,
+             ipra >> 4 & 0xf, vcr_hi(VCRWDT)),
+            // BSC refresh compare match, shares its priority with the WDT
+            (rtcsr & (RTCSR_CMF | RTCSR_CMIE) == RTCSR_CMF | RTCSR_CMIE,
+             ipra >> 4 & 0xf, vcr_lo(VCRWDT)),
+            // SCI receive error, receive, transmit and transmit end
+            (ssr & SSR_ERRORS != 0 && scr & SCR_RIE != 0,
+             iprb >> 12 & 0xf, vcr_hi(VCRA)),
+            (ssr & SSR_RDRF != 0 && scr & SCR_RIE != 0,
+             iprb >> 12 & 0xf, vcr_lo(VCRA)),
+            (ssr & SSR_TDRE != 0 && scr & SCR_TIE != 0,
+             iprb >> 12 & 0xf, vcr_hi(VCRB)),
+            (ssr & SSR_TEND != 0 && scr & SCR_TEIE != 0,
+             iprb >> 12 & 0xf, vcr_lo(VCRB)),
+            // FRT input capture, compare match A or B, and overflow
+            (ftcsr & FTCSR_ICF != 0, iprb >> 8 & 0xf, vcr_hi(VCRC)),
+            (ftcsr & (FTCSR_OCFA | FTCSR_OCFB) != 0,
+             iprb >> 8 & 0xf, vcr_lo(VCRC)),
+            (ftcsr & FTCSR_OVF != 0, iprb >> 8 & 0xf, vcr_hi(VCRD)),
+        ]
+    }
+
+    // The request with the highest level wins, the order of the sources
+    // settles ties. Level 0 never gets through.
+    fn intc_irq(&self) -> Option<(u32, u32)> {
+        self.irq_sources().iter()
+            .filter(|&&(req, level, _)| req && level > 0)
+            .fold(None, |best: Option<(u32, u32)>, &(_, level, vector)| {
+                match best {
+                    Some((best_level, _)) if best_level >= level => best,
+                    _ => Some((level, vector)),
+                }
+            })
+    }
+
+    // SCI
+
+    // Connect the serial line to the host. Bytes sent by the SCI are written
+    // to output, and bytes read from input are received by it, as fast as
+    // the bit rate allows. Input is read on a thread of its own, so it can
+    // block, as with a pipe or a PTY.
+    pub fn connect_sci<R, W>(&mut self, input: R, output: W)
+        where R: Read + Send + 'static, W: Write + Send + 'static
+    {
+        let (send, recv) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(input).bytes() {
+                match byte {
+                    Ok(byte) => if send.send(byte).is_err() { break; },
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                    Err(_) => break
+                }
+            }
+        });
+        self.sci_in = Some(recv);
+        self.sci_out = Some(Box::new(output));
+    }
+
+    // Cycles it takes to send or receive a frame. With the external clock
+    // selected in SCR, the bit rate is still taken from BRR.
+    fn sci_frame_cycles(&self) -> u64 {
+        let smr = self.regs.get8(SMR);
+        // SMR CKS selects φ, φ/4, φ/16 or φ/64
+        let clock = 1 << (2 * (smr & 0x3));
+        let n = self.regs.get8(BRR) as u64 + 1;
+        if smr & SMR_CA != 0 {
+            return 4 * clock * n * 8;
+        }
+        let data = if smr & SMR_CHR != 0 { 7 } else { 8 };
+        let parity = if smr & SMR_PE != 0 { 1 } else { 0 };
+        let stop = if smr & SMR_STOP != 0 { 2 } else { 1 };
+        32 * clock * n * (1 + data + parity + stop)
+    }
+
+    fn sci_tick(&mut self, cycles: u64) {
+        if self.regs.stopped(Sci) {
+            self.sci_tx = None;
+            self.sci_rx = None;
+            return;
+        }
+        let scr = self.regs.get8(SCR);
+        let frame = self.sci_frame_cycles();
+        if scr & SCR_TE != 0 {
+            self.sci_transmit(cycles, frame);
+        }
+        if scr & SCR_RE != 0 {
+            self.sci_receive(cycles, frame);
+        }
+    }
+
+    // Send what's in TDR once the frame before it is out. TDR is free again
+    // as soon as sending starts, TEND is set when there's nothing left.
+    fn sci_transmit(&mut self, mut cycles: u64, frame: u64) {
+        loop {
+            match self.sci_tx {
+                Some((byte, left)) if left > cycles => {
+                    self.sci_tx = Some((byte, left - cycles));
+                    return;
+                },
+                Some((byte, left)) => {
+                    cycles -= left;
+                    self.sci_tx = None;
+                    self.sci_send(byte);
+                },
+                None => {}
+            }
+            let ssr = self.regs.get8(SSR);
+            if ssr & SSR_TDRE != 0 {
+                self.regs.set8(SSR, ssr | SSR_TEND);
+                return;
+            }
+            self.regs.set8(SSR, ssr | SSR_TDRE);
+            self.sci_tx = Some((self.regs.get8(TDR), frame));
+        }
+    }
+
+    // The host going away doesn't stop the program from sending.
+    fn sci_send(&mut self, byte: u8) {
+        let byte = if self.regs.get8(SMR) & (SMR_CA | SMR_CHR) == SMR_CHR {
+            byte & 0x7f
+        } else {
+            byte
+        };
+        if let Some(ref mut out) = self.sci_out {
+            let _ = out.write_all(&[byte]).and_then(|_| out.flush());
+        }
+    }
+
+    // Receive the bytes from the host one frame at a time. A frame that
+    // comes in before RDR is read is lost, and sets ORER, and nothing more
+    // is received until the error flags are cleared.
+    fn sci_receive(&mut self, mut cycles: u64, frame: u64) {
+        loop {
+            match self.sci_rx {
+                Some((byte, left)) if left > cycles => {
+                    self.sci_rx = Some((byte, left - cycles));
+                    return;
+                },
+                Some((byte, left)) => {
+                    cycles -= left;
+                    self.sci_rx = None;
+                    let ssr = self.regs.get8(SSR);
+                    if ssr & SSR_RDRF != 0 {
+                        self.regs.set8(SSR, ssr | SSR_ORER);
+                    } else {
+                        let mask = if self.regs.get8(SMR) &
+                                      (SMR_CA | SMR_CHR) == SMR_CHR { 0x7f }
+                                   else { 0xff };
+                        self.regs.set8(RDR, byte & mask);
+                        self.regs.set8(SSR, ssr | SSR_RDRF);
+                    }
+                },
+                None => {}
+            }
+            if self.regs.get8(SSR) & SSR_ERRORS != 0 {
+                return;
+            }
+            match self.sci_in.as_ref().and_then(|recv| recv.try_recv().ok()) {
+                Some(byte) => self.sci_rx = Some((byte, frame)),
+                None => return
+            }
+        }
+    }
+
+    // DIVU
+
+    // Divide DVDNTH:DVDNTL by DVSR, signed. The quotient goes to DVDNTL and
+    // DVDNT, the remainder to DVDNTH. If the quotient doesn't fit in 32 bits
+    // OVF is set, and with OVFIE clear the quotient saturates. With OVFIE
+    // set the division is aborted, leaving the dividend, and the overflow
+    // interrupt is requested.
+    fn divide(&mut self) {
+        let dividend = ((self.regs.get(DVDNTH, 4) as u64) << 32 |
+                        self.regs.get(DVDNTL, 4) as u64) as i64;
+        let divisor = self.regs.get(DVSR, 4) as i32 as i64;
+        self.divu_owed = Some(self.divu_busy);
+        self.divu_busy += DIVU_LATENCY;
+
+        match dividend.checked_div(divisor) {
+            Some(quot) if quot as i32 as i64 == quot => {
+                self.regs.set(DVDNTH, 4, (dividend % divisor) as u32);
+                self.regs.set(DVDNTL, 4, quot as u32);
+                self.regs.set(DVDNT, 4, quot as u32);
+            },
+            _ => {
+                let dvcr = self.regs.get(DVCR, 4) | DVCR_OVF;
+                self.regs.set(DVCR, 4, dvcr);
+                if dvcr & DVCR_OVFIE == 0 {
+                    let quot = if (dividend < 0) != (divisor < 0) {
+                        0x80000000
+                    } else {
+                        0x7fffffff
+                    };
+                    self.regs.set(DVDNTL, 4, quot);
+                    self.regs.set(DVDNT, 4, quot);
+                }
+            }
+        }
+    }
+
+    // The results are there right away, but an access to the DIVU while
+    // it's still dividing has to wait until it's done. Returns the cycles
+    // waited.
+    fn divu_sync(&mut self) -> u64 {
+        match self.divu_owed.take() {
+            Some(wait) => wait,
+            None => mem::replace(&mut self.divu_busy, 0)
+        }
+    }
+
+    // DMAC
+
+    // Drive the DREQ pin of a DMA channel. Depending on CHCR, a request is
+    // an active level or an active edge.
+    pub fn set_dreq(&mut self, channel: usize, level: bool) {
+        let chcr = self.regs.get(CHCR0 + channel as u32 * 0x10, 4);
+        let active = chcr & CHCR_DL != 0;
+        if level != self.dreq[channel] && level == active {
+            self.dreq_edge[channel] = true;
+        }
+        self.dreq[channel] = level;
+    }
+
+    fn dma_requested(&self, ch: usize) -> bool {
+        let chcr = self.regs.get(CHCR0 + ch as u32 * 0x10, 4);
+        let dmaor = self.regs.get(DMAOR, 4);
+        let enabled = chcr & (CHCR_DE | CHCR_TE) == CHCR_DE &&
+            dmaor & (DMAOR_DME | DMAOR_AE | DMAOR_NMIF) == DMAOR_DME;
+        if !enabled {
+            return false;
+        }
+        if chcr & CHCR_AR != 0 {
+            return true;
+        }
+        // DRCR selects the request source
+        match self.regs.get8(DRCR0 + ch as u32) & 0x3 {
+            0 if chcr & CHCR_DS != 0 => self.dreq_edge[ch],
+            0 => self.dreq[ch] == (chcr & CHCR_DL != 0),
+            1 => self.regs.get8(SSR) & SSR_RDRF != 0,
+            2 => self.regs.get8(SSR) & SSR_TDRE != 0,
+            _ => false
+        }
+    }
+
+    // Serve the requesting channels, highest priority first. In cycle steal
+    // mode a channel does one transfer unit, and gives the bus back to the
+    // cpu. In burst mode it keeps the bus for as long as it is requested.
+    // Returns the bus cycles used.
+    fn dma_run(&mut self) -> u64 {
+        let order = if self.regs.get(DMAOR, 4) & DMAOR_PR != 0 {
+            [1 - self.dma_last, self.dma_last]
+        } else {
+            [0, 1]
+        };
+        let mut cycles = 0;
+        for &ch in &order {
+            let burst = self.regs.get(CHCR0 + ch as u32 * 0x10, 4) &
+                        CHCR_TB != 0;
+            while self.dma_requested(ch) {
+                cycles += self.dma_unit(ch);
+                self.dma_last = ch;
+                if !burst {
+                    break;
+                }
+            }
+        }
+        cycles
+    }
+
+    // Transfer a single unit on a channel, returning the bus cycles it took.
+    fn dma_unit(&mut self, ch: usize) -> u64 {
+        let base = SAR0 + ch as u32 * 0x10;
+        let chcr = self.regs.get(base + 0xc, 4);
+        let mut sar = self.regs.get(base, 4);
+        let mut dar = self.regs.get(base + 0x4, 4);
+        let mut tcr = self.regs.get(base + 0x8, 4);
+
+        // a 16 byte unit is moved as 4 longs, and counts as 4 transfers
+        let (size, count) = match chcr >> 10 & 0x3 {
+            0 => (1, 1),
+            1 => (2, 1),
+            2 => (4, 1),
+            _ => (4, 4),
+        };
+        // a misaligned address stops the DMAC, and raises a cpu address
+        // error
+        let align = size * count - 1;
+        if sar & align != 0 || dar & align != 0 {
+            let dmaor = self.regs.get(DMAOR, 4);
+            self.regs.set(DMAOR, 4, dmaor | DMAOR_AE);
+            self.dma_addr_error = true;
+            return 0;
+        }
+
+        // SM and DM: fixed, increment or decrement
+        let step = |mode| match mode & 0x3 {
+            1 => size,
+            2 => size.wrapping_neg(),
+            _ => 0,
+        };
+        let (src_step, dst_step) = (step(chcr >> 12), step(chcr >> 14));
+
+        // one bus cycle per access, plus what the memory adds
+        let mut cycles = 0;
+        let single = chcr & CHCR_TA != 0;
+        for _ in 0..count {
+            if !single {
+                let val = self.dma_read(sar, size, &mut cycles);
+                self.dma_write(dar, size, val, &mut cycles);
+            } else if chcr & CHCR_AM != 0 {
+                let val = self.user.dack_read(ch, size);
+                self.dma_write(dar, size, val, &mut cycles);
+            } else {
+                let val = self.dma_read(sar, size, &mut cycles);
+                self.user.dack_write(ch, size, val);
+            }
+            sar = sar.wrapping_add(src_step);
+            dar = dar.wrapping_add(dst_step);
+        }
+
+        // a count of 0 means 0x1000000
+        if tcr == 0 {
+            tcr = 0x1000000;
+        }
+        tcr = tcr.saturating_sub(count);
+        let te = if tcr == 0 { CHCR_TE } else { 0 };
+        self.regs.set(base, 4, sar);
+        self.regs.set(base + 0x4, 4, dar);
+        self.regs.set(base + 0x8, 4, tcr);
+        self.regs.set(base + 0xc, 4, chcr | te);
+
+        // the request is served, for the on-chip sources by the transfer
+        // from RDR or to TDR
+        self.dreq_edge[ch] = false;
+        let ssr = self.regs.get8(SSR);
+        match self.regs.get8(DRCR0 + ch as u32) & 0x3 {
+            1 => self.regs.set8(SSR, ssr & !SSR_RDRF),
+            2 => self.regs.set8(SSR, ssr & !(SSR_TDRE | SSR_TEND)),
+            _ => {}
+        }
+
+        cycles
+    }
+
+    fn dma_read(&mut self, addr: u32, size: u32, cycles: &mut u64) -> u32 {
+        let val = match size {
+            1 => self.read_byte(addr) as u32,
+            2 => self.read_word(addr) as u32,
+            _ => self.read_long(addr),
+        };
+        *cycles += 1 + self.access_cycles(addr, size);
+        val
+    }
+
+    fn dma_write(&mut self, addr: u32, size: u32, val: u32,
+                 cycles: &mut u64) {
+        match size {
+            1 => self.write_byte(addr, val as u8),
+            2 => self.write_word(addr, val as u16),
+            _ => self.write_long(addr, val),
+        }
+        *cycles += 1 + self.access_cycles(addr, size);
+    }
+
+    // WDT
+
+    fn wdt_tick(&mut self, cycles: u64) {
+        let wtcsr = self.regs.get8(WTCSR);
+        if wtcsr & WTCSR_TME == 0 {
+            return;
+        }
+        // WTCSR clock select, dividing the cpu clock
+        let div = [2, 64, 128, 256, 512, 1024, 4096, 8192]
+                  [(wtcsr & 0x7) as usize];
+        self.wdt_prescale += cycles;
+        while self.wdt_prescale >= div {
+            self.wdt_prescale -= div;
+            self.wdt_count();
+        }
+    }
+
+    // Advance WTCNT by one. On overflow the interval timer raises its
+    // interrupt, the watchdog sets WOVF and resets the chip if RSTE is set.
+    fn wdt_count(&mut self) {
+        let wtcnt = self.regs.get8(WTCNT).wrapping_add(1);
+        self.regs.set8(WTCNT, wtcnt);
+        if wtcnt != 0 {
+            return;
+        }
+        let wtcsr = self.regs.get8(WTCSR);
+        if wtcsr & WTCSR_WT == 0 {
+            self.regs.set8(WTCSR, wtcsr | WTCSR_OVF);
+            return;
+        }
+        let rstcsr = self.regs.get8(RSTCSR);
+        self.regs.set8(RSTCSR, rstcsr | RSTCSR_WOVF);
+        if rstcsr & RSTCSR_RSTE != 0 {
+            self.wdt_reset = Some(if rstcsr & RSTCSR_RSTS != 0 {
+                Reset::Manual
+            } else {
+                Reset::PowerOn
+            });
+        }
+    }
+
+    // FRT
+
+    // An edge on the FTCI pin. It clocks the FRC when TCR selects the
+    // external clock.
+    pub fn frt_external_clock(&mut self) {
+        if !self.regs.stopped(Frt) && self.regs.get8(TCR) & 0x3 == 0x3 {
+            self.frt_count();
+        }
+    }
+
+    // Drive the FTI pin. The edge selected by TCR IEDGA captures FRC in
+    // FICR.
+    pub fn set_fti(&mut self, level: bool) {
+        let rising = self.regs.get8(TCR) & TCR_IEDGA != 0;
+        if level != self.fti_pin && level == rising &&
+           !self.regs.stopped(Frt) {
+            let frc = self.regs.get16(FRCH);
+            self.regs.set16(FICRH, frc);
+            let ftcsr = self.regs.get8(FTCSR);
+            self.regs.set8(FTCSR, ftcsr | FTCSR_ICF);
         }
+        self.fti_pin = level;
     }
 
-    // TODO: reset fn
+    // Pulse FTI away from its current level and back, which gives an input
+    // capture whichever edge is selected.
+    pub fn pulse_fti(&mut self) {
+        let level = self.fti_pin;
+        self.set_fti(!level);
+        self.set_fti(level);
+    }
+
+    pub fn fto(&self, pin: FrtPin) -> bool {
+        self.fto[pin as usize]
+    }
+
+    // the FTOA/FTOB level changes since the last call, oldest first
+    pub fn take_fto_changes(&mut self) -> Vec<(FrtPin, bool)> {
+        mem::take(&mut self.fto_changes)
+    }
+
+    fn set_fto(&mut self, pin: FrtPin, level: bool) {
+        if self.fto[pin as usize] != level {
+            self.fto[pin as usize] = level;
+            self.fto_changes.push((pin, level));
+        }
+    }
+
+    fn frt_tick(&mut self, cycles: u64) {
+        if self.regs.stopped(Frt) {
+            return;
+        }
+        // TCR clock select, dividing the cpu clock
+        let div = match self.regs.get8(TCR) & 0x3 {
+            0 => 8,
+            1 => 32,
+            2 => 128,
+            _ => return
+        };
+        self.frt_prescale += cycles;
+        while self.frt_prescale >= div {
+            self.frt_prescale -= div;
+            self.frt_count();
+        }
+    }
+
+    // Advance FRC by one. With CCLRA set, the count after a compare match A
+    // is 0 instead.
+    fn frt_count(&mut self) {
+        let ftcsr = self.regs.get8(FTCSR);
+        let ocra = self.regs.get16(OCRH);
+        let old = self.regs.get16(FRCH);
+        let frc = if ftcsr & FTCSR_CCLRA != 0 && old == ocra { 0 }
+                  else { old.wrapping_add(1) };
+        let mut flags = 0;
+        if old == 0xffff && frc == 0 {
+            flags |= FTCSR_OVF;
+        }
+        if frc == ocra {
+            flags |= FTCSR_OCFA;
+        }
+        if frc == self.regs.ocrb {
+            flags |= FTCSR_OCFB;
+        }
+        self.regs.set16(FRCH, frc);
+        self.regs.set8(FTCSR, ftcsr | flags);
+
+        // compare matches drive the output pins to the TOCR levels
+        let tocr = self.regs.get8(TOCR);
+        if flags & FTCSR_OCFA != 0 {
+            self.set_fto(FrtPin::Ftoa, tocr & TOCR_OLVLA != 0);
+        }
+        if flags & FTCSR_OCFB != 0 {
+            self.set_fto(FrtPin::Ftob, tocr & TOCR_OLVLB != 0);
+        }
+    }
+
+    // The WDT regs are written with a word write, with a password in the
+    // upper byte that selects the register.
+    fn write_wdt(&mut self, addr: u32, val: u16) {
+        let data = val as u8;
+        match (addr, val >> 8) {
+            // OVF can only be cleared. Disabling the timer clears WTCNT.
+            (WTCSR, 0xa5) => {
+                let ovf = self.regs.get8(WTCSR) & data & WTCSR_OVF;
+                self.regs.set8(WTCSR, ovf | data & 0x67 | 0x18);
+                if data & WTCSR_TME == 0 {
+                    self.regs.set8(WTCNT, 0);
+                    self.wdt_prescale = 0;
+                }
+            },
+            (WTCSR, 0x5a) => self.regs.set8(WTCNT, data),
+            // writing 0 clears the overflow flag, WOVF
+            (0xfffffe82, 0xa5) => {
+                if data == 0 {
+                    let rstcsr = self.regs.get8(RSTCSR);
+                    self.regs.set8(RSTCSR, rstcsr & 0x7f);
+                }
+            },
+            // RSTE and RSTS
+            (0xfffffe82, 0x5a) => {
+                let rstcsr = self.regs.get8(RSTCSR);
+                self.regs.set8(RSTCSR, rstcsr & 0x9f | data & 0x60);
+            },
+            _ => self.unmapped(addr, 2, true)
+        }
+    }
+
+    fn read_onchip(&self, addr: u32, width: u32) -> u32 {
+        match self.find_access(addr, width, false) {
+            Some((reg, shift)) => {
+                (self.read_reg(reg) >> shift) & width_mask(width)
+            },
+            None => {
+                self.unmapped(addr, width, false);
+                0
+            }
+        }
+    }
+
+    fn write_onchip(&mut self, addr: u32, width: u32, val: u32) {
+        if width == 2 && (addr == WTCSR || addr == 0xfffffe82) {
+            self.write_wdt(addr, val as u16);
+            return;
+        }
+        match self.find_access(addr, width, true) {
+            // stopped modules ignore writes
+            Some((reg, _)) if self.regs.stopped(reg.module) => {},
+            Some((reg, shift)) => {
+                let mask = width_mask(width) << shift;
+                // only read what's not overwritten, as reads can have side
+                // effects
+                let old = if width == reg.size { 0 }
+                          else { self.read_reg(reg) };
+                self.write_reg(reg, old & !mask | (val << shift) & mask);
+            },
+            None => self.unmapped(addr, width, true)
+        }
+    }
 }
 
 
@@ -90,46 +1377,14 @@ impl<U: Bus> Bus for Sh7604Mem<U> {
     // byte access
     fn read_byte(&self, addr: u32) -> u8 {
         match addr {
-            0xe0000000 ... 0xffffffff => {
-                match addr {
-                    // curious but true: these two addresses below
-                    // are shared between registers
-                    0xfffffe14 => {
-                        if self.regs.tocr & 0x10 == 0 { self.regs.ocra_h }
-                        else { self.regs.ocrb_h }
-                    },
-                    0xfffffe15 => {
-                        if self.regs.tocr & 0x10 == 0 { self.regs.ocra_l }
-                        else { self.regs.ocrb_l }
-                    },
-                    0xfffffe16 => self.regs.tcr,
-                    _ => panic!("sh7604 read_byte: {:#010x} not (yet) mapped",
-                                addr)
-                }
-            },
+            0xe0000000 ..= 0xffffffff => self.read_onchip(addr, 1) as u8,
             _ => self.user.read_byte(addr & 0xdfffffff)
         }
     }
 
     fn write_byte(&mut self, addr: u32, val: u8) {
         match addr {
-            0xe0000000 ... 0xffffffff => {
-                match addr {
-                    0xfffffe10 => self.regs.tier = val,
-                    0xfffffe11 => self.regs.ftcsr = val,
-                    0xfffffe14 => {
-                        if self.regs.tocr & 0x10 == 0 { self.regs.ocra_h = val }
-                        else { self.regs.ocrb_h = val }
-                    },
-                    0xfffffe15 => {
-                        if self.regs.tocr & 0x10 == 0 { self.regs.ocra_l = val }
-                        else { self.regs.ocrb_l = val }
-                    },
-                    0xfffffe16 => self.regs.tcr = val,
-                    _ => panic!("sh7604 write_byte: {:#010x} not (yet) mapped",
-                           addr)
-                }
-            },
+            0xe0000000 ..= 0xffffffff => self.write_onchip(addr, 1, val as u32),
             _ => self.user.write_byte(addr & 0xdfffffff, val)
         };
     }
@@ -137,28 +1392,14 @@ impl<U: Bus> Bus for Sh7604Mem<U> {
     // word access
     fn read_word(&self, addr: u32) -> u16 {
         match addr {
-            0xe0000000 ... 0xffffffff => {
-                match addr {
-                    0xfffffe60 => self.regs.iprb,
-                    0xfffffe66 => self.regs.vcrc,
-                    _ => panic!("sh7604 read_word: {:#010x} not (yet) mapped",
-                                addr)
-                }
-            },
+            0xe0000000 ..= 0xffffffff => self.read_onchip(addr, 2) as u16,
             _ => self.user.read_word(addr & 0xdfffffff)
         }
     }
 
     fn write_word(&mut self, addr: u32, val: u16) {
         match addr {
-            0xe0000000 ... 0xffffffff => {
-                match addr {
-                    0xfffffe60 => self.regs.iprb = val,
-                    0xfffffe66 => self.regs.vcrc = val,
-                    _ => panic!("sh7604 write_word: {:#010x} not (yet) mapped",
-                                addr)
-                }
-            },
+            0xe0000000 ..= 0xffffffff => self.write_onchip(addr, 2, val as u32),
             _ => self.user.write_word(addr & 0xdfffffff, val)
         };
     }
@@ -166,20 +1407,1027 @@ impl<U: Bus> Bus for Sh7604Mem<U> {
     // long access
     fn read_long(&self, addr: u32) -> u32 {
         match addr {
-            0xe0000000 ... 0xffffffff => {
-                panic!("sh7604 read_long: no private mem mapped yet")
-            },
+            0xe0000000 ..= 0xffffffff => self.read_onchip(addr, 4),
             _ => self.user.read_long(addr & 0xdfffffff)
         }
     }
 
     fn write_long(&mut self, addr: u32, val: u32) {
         match addr {
-            0xe0000000 ... 0xffffffff => {
-                panic!("sh7604 write_long: {:#010x} not (yet) mapped",
-                       addr)
-            },
+            0xe0000000 ..= 0xffffffff => self.write_onchip(addr, 4, val),
             _ => self.user.write_long(addr & 0xdfffffff, val)
         };
     }
+
+    fn access_cycles(&mut self, addr: u32, size: u32) -> u64 {
+        match addr {
+            0xe0000000 ..= 0xffffffff => match find_reg(addr) {
+                Some((reg, _)) if reg.module == Divu => self.divu_sync(),
+                _ => 0
+            },
+            _ => self.bus_cycles(addr, size)
+        }
+    }
+
+    fn take_address_error(&mut self) -> bool {
+        mem::replace(&mut self.dma_addr_error, false)
+    }
+
+    fn take_reset(&mut self) -> Option<Reset> {
+        let reset = self.wdt_reset.take();
+        self.wdt_resetting = reset.is_some();
+        reset
+    }
+
+    fn take_nmi(&mut self) -> bool {
+        let nmi = self.nmi_pending;
+        self.nmi_pending = false;
+        nmi
+    }
+
+    // The NMI pin is external, so its level is kept. A reset by the watchdog
+    // is internal to the chip: RSTCSR keeps the overflow flag, and the user
+    // bus isn't reset.
+    fn reset(&mut self, kind: Reset) {
+        let rstcsr = self.regs.get8(RSTCSR);
+        self.regs.reset(kind);
+        self.nmi_pending = false;
+        self.dreq_edge = [false; 2];
+        self.dma_last = 1;
+        self.dma_addr_error = false;
+        self.divu_busy = 0;
+        self.divu_owed = None;
+        self.frt_prescale = 0;
+        self.set_fto(FrtPin::Ftoa, false);
+        self.set_fto(FrtPin::Ftob, false);
+        self.fault.set(None);
+        self.sci_tx = None;
+        self.sci_rx = None;
+        self.rtc_prescale = 0;
+        self.wdt_prescale = 0;
+        self.wdt_reset = None;
+        if self.wdt_resetting {
+            self.regs.set8(RSTCSR, rstcsr);
+            self.wdt_resetting = false;
+        } else {
+            self.user.reset(kind);
+        }
+    }
+
+    fn take_fault(&mut self) -> Option<BusFault> {
+        self.fault.take().or_else(|| self.user.take_fault())
+    }
+
+    // DMA transfers hold up the cpu, but the rest of the chip keeps going.
+    fn tick(&mut self, cycles: u64) {
+        self.frt_tick(cycles);
+        self.wdt_tick(cycles);
+        self.sci_tick(cycles);
+        self.rtc_tick(cycles);
+        let stolen = self.dma_run();
+        self.frt_tick(stolen);
+        self.wdt_tick(stolen);
+        self.sci_tick(stolen);
+        self.rtc_tick(stolen);
+        // a division started from outside the cpu owes nothing
+        self.divu_owed = None;
+        self.divu_busy = self.divu_busy.saturating_sub(cycles + stolen);
+        self.stall += stolen;
+        self.user.tick(cycles + stolen);
+    }
+
+    fn take_stall(&mut self) -> u64 {
+        let stall = self.stall;
+        self.stall = 0;
+        stall
+    }
+
+    fn pending_irq(&self) -> Option<(u32, u32)> {
+        self.intc_irq()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::MemAccess;
+    use disasm::Disassemble;
+    use sh2::Sh2;
+    use std::sync::{Arc, Mutex};
+
+    // the cpu side isn't needed to test the on-chip modules
+    struct NoMem;
+
+    impl Bus for NoMem {
+        fn read_byte(&self, _addr: u32) -> u8 { 0 }
+        fn read_word(&self, _addr: u32) -> u16 { 0 }
+        fn read_long(&self, _addr: u32) -> u32 { 0 }
+        fn write_byte(&mut self, _addr: u32, _val: u8) {}
+        fn write_word(&mut self, _addr: u32, _val: u16) {}
+        fn write_long(&mut self, _addr: u32, _val: u32) {}
+    }
+
+    // 64KB of memory, and a device on DACK0 that hands out and takes in
+    // values
+    struct Ram {
+        mem: Vec<u8>,
+        dack_in: u32,
+        dack_out: Vec<u32>,
+    }
+
+    impl Ram {
+        fn new() -> Ram {
+            Ram { mem: vec![0; 0x10000], dack_in: 0, dack_out: Vec::new() }
+        }
+    }
+
+    impl Bus for Ram {
+        fn read_byte(&self, addr: u32) -> u8 {
+            u8::read_mem(&self.mem, addr as usize)
+        }
+        fn read_word(&self, addr: u32) -> u16 {
+            u16::read_mem(&self.mem, addr as usize)
+        }
+        fn read_long(&self, addr: u32) -> u32 {
+            u32::read_mem(&self.mem, addr as usize)
+        }
+        fn write_byte(&mut self, addr: u32, val: u8) {
+            u8::write_mem(&mut self.mem, addr as usize, val)
+        }
+        fn write_word(&mut self, addr: u32, val: u16) {
+            u16::write_mem(&mut self.mem, addr as usize, val)
+        }
+        fn write_long(&mut self, addr: u32, val: u32) {
+            u32::write_mem(&mut self.mem, addr as usize, val)
+        }
+        fn dack_read(&mut self, _channel: usize, _size: u32) -> u32 {
+            self.dack_in += 1;
+            self.dack_in
+        }
+        fn dack_write(&mut self, _channel: usize, _size: u32, val: u32) {
+            self.dack_out.push(val);
+        }
+    }
+
+    #[test]
+    fn nmi_falling_edge() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        assert_eq!(mem.read_word(0xfffffee0), 0x8000);
+        mem.set_nmi(true);
+        assert!(!mem.take_nmi());
+        mem.set_nmi(false);
+        assert_eq!(mem.read_word(0xfffffee0), 0x0000);
+        assert!(mem.take_nmi());
+        assert!(!mem.take_nmi());
+        mem.set_nmi(true);
+        assert!(!mem.take_nmi());
+    }
+
+    #[test]
+    fn nmi_rising_edge() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_byte(0xfffffee0, 0xff);
+        // NMIL isn't writable
+        assert_eq!(mem.read_byte(0xfffffee0), 0x81);
+        mem.set_nmi(false);
+        assert!(!mem.take_nmi());
+        mem.set_nmi(true);
+        assert!(mem.take_nmi());
+    }
+
+    #[test]
+    fn reset_regs() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_word(0xfffffee0, 0x0101);
+        mem.write_byte(0xfffffe16, 0x03);
+        mem.reset(Reset::Manual);
+        assert_eq!(mem.read_word(0xfffffee0), 0x8000);
+        assert_eq!(mem.read_byte(0xfffffe16), 0x00);
+    }
+
+    #[test]
+    fn manual_reset_keeps_bsc() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_long(0xffffffe8, 0xa55a1234);
+        mem.write_word(0xfffffe82, 0x5a40);
+        mem.write_byte(0xfffffe92, 0x01);
+        mem.reset(Reset::Manual);
+        assert_eq!(mem.read_long(0xffffffe8), 0x1234);
+        assert_eq!(mem.read_byte(0xfffffe83), 0x5f);
+        assert_eq!(mem.read_byte(0xfffffe92), 0x00);
+        mem.reset(Reset::PowerOn);
+        assert_eq!(mem.read_long(0xffffffe8), 0xaaff);
+        assert_eq!(mem.read_byte(0xfffffe83), 0x1f);
+    }
+
+    #[test]
+    fn module_standby() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_byte(0xfffffe10, 0x8e);
+        mem.write_byte(0xfffffe01, 0x10);
+        mem.write_long(0xffffff00, 7);
+        // stop the FRT and DIVU
+        mem.write_byte(0xfffffe91, 0x06);
+        assert_eq!(mem.read_byte(0xfffffe10), 0x01);
+        assert_eq!(mem.read_long(0xffffff00), 7);
+        mem.write_byte(0xfffffe10, 0x8e);
+        mem.write_long(0xffffff00, 8);
+        assert_eq!(mem.read_byte(0xfffffe10), 0x01);
+        assert_eq!(mem.read_long(0xffffff00), 7);
+        assert_eq!(mem.read_byte(0xfffffe01), 0x10);
+        // and start them again
+        mem.write_byte(0xfffffe91, 0x00);
+        mem.write_byte(0xfffffe10, 0x8e);
+        assert_eq!(mem.read_byte(0xfffffe10), 0x8f);
+    }
+
+    #[test]
+    fn unmapped_fault() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        assert_eq!(mem.read_long(0xfffffe40), 0);
+        assert_eq!(mem.take_fault(),
+                   Some(BusFault { addr: 0xfffffe40, size: 4, write: false }));
+        assert_eq!(mem.take_fault(), None);
+        mem.write_word(0xfffffe00, 0);
+        assert_eq!(mem.take_fault(),
+                   Some(BusFault { addr: 0xfffffe00, size: 2, write: true }));
+    }
+
+    #[test]
+    fn unmapped_record() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.set_unmapped_policy(UnmappedPolicy::Record);
+        assert_eq!(mem.read_byte(0xfffffe40), 0);
+        mem.write_long(0xfffffe44, 1);
+        assert_eq!(mem.take_fault(), None);
+        assert_eq!(mem.take_unmapped(),
+                   vec![BusFault { addr: 0xfffffe40, size: 1, write: false },
+                        BusFault { addr: 0xfffffe44, size: 4, write: true }]);
+        assert!(mem.take_unmapped().is_empty());
+    }
+
+    #[test]
+    fn unmapped_log() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.set_unmapped_policy(UnmappedPolicy::Log);
+        assert_eq!(mem.read_word(0xffffff00), 0);
+        assert_eq!(mem.read_word(0xfffffe40), 0);
+        assert_eq!(mem.take_fault(), None);
+        assert!(mem.take_unmapped().is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn unmapped_panic() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.set_unmapped_policy(UnmappedPolicy::Panic);
+        mem.read_long(0xfffffe40);
+    }
+
+    // register map
+
+    #[test]
+    fn reset_values() {
+        let mem = Sh7604Mem::new(NoMem);
+        assert_eq!(mem.read_byte(0xfffffe04), 0x84);
+        assert_eq!(mem.read_byte(0xfffffe17), 0xe0);
+        assert_eq!(mem.read_byte(0xfffffe80), 0x18);
+        assert_eq!(mem.read_byte(0xfffffe83), 0x1f);
+        assert_eq!(mem.read_long(0xffffffe8), 0x0000aaff);
+        assert_eq!(mem.read_long(0xffffffa0), 0xdeadbeef);
+    }
+
+    #[test]
+    fn word_reg_as_bytes() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_word(0xfffffe60, 0x1234);
+        assert_eq!(mem.read_byte(0xfffffe60), 0x12);
+        assert_eq!(mem.read_byte(0xfffffe61), 0x34);
+        mem.write_byte(0xfffffe61, 0x56);
+        assert_eq!(mem.read_word(0xfffffe60), 0x1256);
+        assert_eq!(mem.take_fault(), None);
+    }
+
+    #[test]
+    fn long_only_regs() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_long(0xffffff0c, 0x40);
+        assert_eq!(mem.read_long(0xffffff0c), 0x40);
+        assert_eq!(mem.take_fault(), None);
+        mem.write_word(0xffffff0e, 0x41);
+        assert_eq!(mem.take_fault(),
+                   Some(BusFault { addr: 0xffffff0e, size: 2, write: true }));
+        assert_eq!(mem.read_long(0xffffff0c), 0x40);
+        mem.read_byte(0xffffffa3);
+        assert!(mem.take_fault().is_some());
+        // DVCR also takes words
+        assert_eq!(mem.read_word(0xffffff0a), 0);
+        assert_eq!(mem.take_fault(), None);
+    }
+
+    #[test]
+    fn shared_ocr() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_byte(0xfffffe14, 0x12);
+        mem.write_byte(0xfffffe15, 0x00);
+        mem.write_byte(0xfffffe17, 0xf0);
+        assert_eq!(mem.read_byte(0xfffffe14), 0xff);
+        mem.write_byte(0xfffffe14, 0x34);
+        mem.write_byte(0xfffffe15, 0x56);
+        mem.write_byte(0xfffffe17, 0xe0);
+        assert_eq!(mem.read_byte(0xfffffe14), 0x12);
+        assert_eq!(mem.regs.ocrb, 0x3456);
+    }
+
+    #[test]
+    fn divu_mirror() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_long(0xffffff20, 3);
+        assert_eq!(mem.read_long(0xffffff00), 3);
+        mem.write_long(0xffffff10, 5);
+        assert_eq!(mem.read_long(0xffffff18), 5);
+        assert_eq!(mem.read_long(0xffffff38), 5);
+    }
+
+    #[test]
+    fn bsc_password() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_long(0xffffffe8, 0x00001234);
+        assert_eq!(mem.read_long(0xffffffe8), 0x0000aaff);
+        mem.write_long(0xffffffe8, 0xa55a1234);
+        assert_eq!(mem.read_long(0xffffffe8), 0x00001234);
+        assert_eq!(mem.read_word(0xffffffea), 0x1234);
+        mem.write_word(0xffffffea, 0);
+        assert!(mem.take_fault().is_some());
+    }
+
+    #[test]
+    fn bsc_wait_states() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        // after reset area 0 has long waits, of 6
+        assert_eq!(mem.access_cycles(0x0, 4), 7);
+        mem.set_area0_width(2);
+        assert_eq!(mem.access_cycles(0x0, 2), 7);
+        assert_eq!(mem.access_cycles(0x0, 4), 15);
+        // cache hits are free, cache through isn't
+        mem.write_byte(0xfffffe92, 0x01);
+        assert_eq!(mem.access_cycles(0x0, 4), 0);
+        assert_eq!(mem.access_cycles(0x20000000, 4), 15);
+        mem.write_byte(0xfffffe92, 0x00);
+        // no waits, and an 8 bit bus in area 1
+        mem.write_long(0xffffffe8, 0xa55a0000);
+        mem.write_long(0xffffffe4, 0xa55a00f4);
+        assert_eq!(mem.access_cycles(0x02000000, 1), 1);
+        assert_eq!(mem.access_cycles(0x02000000, 4), 7);
+        // SDRAM in area 3, CAS latency 2
+        mem.write_long(0xffffffe0, 0xa55a03f2);
+        mem.write_long(0xffffffe8, 0xa55a0080);
+        assert_eq!(mem.access_cycles(0x06000000, 4), 4);
+        // on-chip accesses don't go through the BSC
+        assert_eq!(mem.access_cycles(0xfffffe92, 1), 0);
+    }
+
+    #[test]
+    fn bsc_timed_by_cpu_only() {
+        let mut mem = Sh7604Mem::new(Ram::new());
+        for i in 0..4 {
+            mem.user.write_word(i * 2, 0x0009);
+        }
+        let mut cpu = Sh2::new();
+        cpu.reset(0, 0x1000);
+        // after reset area 0 has long waits, so a fetch takes 8
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.get_cycles(), 8);
+        // looking at memory from outside doesn't hold up the cpu
+        Disassemble::new().disassemble_range(&mut mem, 0, 8, 2);
+        mem.read_long(0x100);
+        mem.tick(0);
+        assert_eq!(mem.take_stall(), 0);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.get_cycles(), 16);
+    }
+
+    #[test]
+    fn bsc_refresh_irq() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_word(0xfffffee2, 0x0050);
+        mem.write_word(0xfffffee4, 0x0045);
+        // compare match every 3 counts at φ/4
+        mem.write_long(0xfffffff8, 0xa55a0003);
+        mem.write_long(0xfffffff0, 0xa55a0048);
+        mem.tick(11);
+        assert_eq!(mem.read_long(0xfffffff4), 2);
+        assert_eq!(mem.pending_irq(), None);
+        mem.tick(1);
+        assert_eq!(mem.read_long(0xfffffff4), 0);
+        assert_eq!(mem.read_long(0xfffffff0), 0xc8);
+        assert_eq!(mem.pending_irq(), Some((5, 0x45)));
+        // CMF can only be cleared
+        mem.write_long(0xfffffff0, 0xa55a0048);
+        assert_eq!(mem.pending_irq(), None);
+        mem.write_long(0xfffffff0, 0xa55a00c8);
+        assert_eq!(mem.read_long(0xfffffff0), 0x48);
+    }
+
+    #[test]
+    fn wdt_password() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_word(0xfffffe80, 0x5a42);
+        mem.write_word(0xfffffe80, 0xa520);
+        assert_eq!(mem.read_byte(0xfffffe81), 0x42);
+        assert_eq!(mem.read_byte(0xfffffe80), 0x38);
+        mem.write_word(0xfffffe82, 0x5a40);
+        assert_eq!(mem.read_byte(0xfffffe83), 0x5f);
+        mem.write_byte(0xfffffe81, 0);
+        assert!(mem.take_fault().is_some());
+        assert_eq!(mem.read_byte(0xfffffe81), 0x42);
+    }
+
+    #[test]
+    fn wdt_interval_timer() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_word(0xfffffee2, 0x0060);
+        mem.write_word(0xfffffee4, 0x4400);
+        // φ/64, from 0xfe
+        mem.write_word(0xfffffe80, 0x5afe);
+        mem.write_word(0xfffffe80, 0xa521);
+        mem.tick(127);
+        assert_eq!(mem.read_byte(0xfffffe81), 0xff);
+        assert_eq!(mem.pending_irq(), None);
+        mem.tick(1);
+        assert_eq!(mem.read_byte(0xfffffe81), 0x00);
+        assert_eq!(mem.read_byte(0xfffffe80), 0xb9);
+        assert_eq!(mem.pending_irq(), Some((6, 0x44)));
+        assert_eq!(mem.take_reset(), None);
+        // OVF is cleared by writing 0, and stopping the timer clears WTCNT
+        mem.tick(64);
+        mem.write_word(0xfffffe80, 0xa501);
+        assert_eq!(mem.read_byte(0xfffffe80), 0x19);
+        assert_eq!(mem.read_byte(0xfffffe81), 0x00);
+        assert_eq!(mem.pending_irq(), None);
+    }
+
+    struct ResetMem {
+        reset: Option<Reset>,
+    }
+
+    impl Bus for ResetMem {
+        fn read_byte(&self, _addr: u32) -> u8 { 0 }
+        fn read_word(&self, _addr: u32) -> u16 { 0 }
+        fn read_long(&self, _addr: u32) -> u32 { 0 }
+        fn write_byte(&mut self, _addr: u32, _val: u8) {}
+        fn write_word(&mut self, _addr: u32, _val: u16) {}
+        fn write_long(&mut self, _addr: u32, _val: u32) {}
+        fn reset(&mut self, kind: Reset) {
+            self.reset = Some(kind);
+        }
+    }
+
+    #[test]
+    fn wdt_reset() {
+        let mut mem = Sh7604Mem::new(ResetMem { reset: None });
+        // watchdog mode, φ/2, no reset
+        mem.write_word(0xfffffe80, 0x5aff);
+        mem.write_word(0xfffffe80, 0xa560);
+        mem.tick(2);
+        assert_eq!(mem.read_byte(0xfffffe83), 0x9f);
+        assert_eq!(mem.read_byte(0xfffffe80), 0x78);
+        assert_eq!(mem.take_reset(), None);
+
+        // manual reset
+        mem.write_word(0xfffffe82, 0xa500);
+        mem.write_word(0xfffffe82, 0x5a60);
+        mem.write_word(0xfffffe80, 0x5aff);
+        mem.tick(2);
+        assert_eq!(mem.take_reset(), Some(Reset::Manual));
+        mem.reset(Reset::Manual);
+        assert_eq!(mem.read_byte(0xfffffe80), 0x18);
+        assert_eq!(mem.read_byte(0xfffffe83), 0xff);
+        assert_eq!(mem.user.reset, None);
+
+        // power-on reset, which keeps WOVF too
+        mem.write_word(0xfffffe82, 0x5a40);
+        mem.write_word(0xfffffe80, 0x5aff);
+        mem.write_word(0xfffffe80, 0xa560);
+        mem.tick(2);
+        assert_eq!(mem.take_reset(), Some(Reset::PowerOn));
+        mem.reset(Reset::PowerOn);
+        assert_eq!(mem.read_byte(0xfffffe83), 0xdf);
+        assert_eq!(mem.user.reset, None);
+        // unlike one from outside
+        mem.reset(Reset::PowerOn);
+        assert_eq!(mem.read_byte(0xfffffe83), 0x1f);
+        assert_eq!(mem.user.reset, Some(Reset::PowerOn));
+    }
+
+    // FRT
+
+    #[test]
+    fn frt_temp_latch() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_byte(0xfffffe12, 0x12);
+        // the high byte only goes in together with the low byte
+        assert_eq!(mem.regs.get16(FRCH), 0x0000);
+        mem.write_byte(0xfffffe13, 0x34);
+        assert_eq!(mem.regs.get16(FRCH), 0x1234);
+        assert_eq!(mem.read_byte(0xfffffe12), 0x12);
+        mem.regs.set16(FRCH, 0x5678);
+        // the low byte was latched on reading the high byte
+        assert_eq!(mem.read_byte(0xfffffe13), 0x34);
+    }
+
+    #[test]
+    fn frt_counts() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.tick(8 * 10 + 7);
+        assert_eq!(mem.regs.get16(FRCH), 10);
+        mem.tick(1);
+        assert_eq!(mem.regs.get16(FRCH), 11);
+        // φ/32
+        mem.write_byte(0xfffffe16, 0x01);
+        mem.tick(64);
+        assert_eq!(mem.regs.get16(FRCH), 13);
+        // external clock
+        mem.write_byte(0xfffffe16, 0x03);
+        mem.tick(1000);
+        mem.frt_external_clock();
+        assert_eq!(mem.regs.get16(FRCH), 14);
+        // stopped in module standby
+        mem.write_byte(0xfffffe16, 0x00);
+        mem.write_byte(0xfffffe91, 0x02);
+        mem.tick(1000);
+        assert_eq!(mem.regs.get16(FRCH), 0);
+    }
+
+    #[test]
+    fn frt_compare_match_clear() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_byte(0xfffffe14, 0x00);
+        mem.write_byte(0xfffffe15, 0x03);
+        mem.write_byte(0xfffffe11, 0x01);
+        mem.tick(8 * 3);
+        assert_eq!(mem.read_byte(0xfffffe11), 0x09);
+        mem.tick(8);
+        assert_eq!(mem.regs.get16(FRCH), 0);
+        // clear the flag
+        mem.write_byte(0xfffffe11, 0x01);
+        assert_eq!(mem.read_byte(0xfffffe11), 0x01);
+        mem.tick(8 * 3);
+        assert_eq!(mem.read_byte(0xfffffe11), 0x09);
+    }
+
+    #[test]
+    fn frt_overflow_irq() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_word(0xfffffe60, 0x0500);
+        mem.write_word(0xfffffe66, 0x4142);
+        mem.write_word(0xfffffe68, 0x4300);
+        mem.write_byte(0xfffffe12, 0xff);
+        mem.write_byte(0xfffffe13, 0xfe);
+        mem.tick(8 * 2);
+        // passing 0xffff also matched OCRA and OCRB
+        assert_eq!(mem.read_byte(0xfffffe11), 0x0e);
+        assert_eq!(mem.pending_irq(), None);
+        mem.write_byte(0xfffffe10, 0x02);
+        assert_eq!(mem.pending_irq(), Some((5, 0x43)));
+        // compare match comes first
+        mem.write_byte(0xfffffe10, 0x06);
+        assert_eq!(mem.pending_irq(), Some((5, 0x42)));
+        mem.write_byte(0xfffffe11, 0x00);
+        assert_eq!(mem.pending_irq(), None);
+    }
+
+    #[test]
+    fn frt_input_capture() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.regs.set16(FRCH, 0x1234);
+        // falling edge by default
+        mem.set_fti(true);
+        assert_eq!(mem.read_byte(0xfffffe11), 0x00);
+        mem.set_fti(false);
+        assert_eq!(mem.read_byte(0xfffffe11), 0x80);
+        assert_eq!(mem.read_byte(0xfffffe18), 0x12);
+        assert_eq!(mem.read_byte(0xfffffe19), 0x34);
+        // rising edge
+        mem.write_byte(0xfffffe11, 0x00);
+        mem.write_byte(0xfffffe16, 0x80);
+        mem.regs.set16(FRCH, 0x5678);
+        mem.pulse_fti();
+        assert_eq!(mem.read_byte(0xfffffe11), 0x80);
+        assert_eq!(mem.regs.get16(FICRH), 0x5678);
+        // the capture interrupt
+        mem.write_word(0xfffffe60, 0x0300);
+        mem.write_word(0xfffffe66, 0x6000);
+        mem.write_byte(0xfffffe10, 0x80);
+        assert_eq!(mem.pending_irq(), Some((3, 0x60)));
+    }
+
+    #[test]
+    fn frt_output_compare_pins() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        // OCRA = 2, OCRB = 4, FTOA goes high on a match, FTOB low
+        mem.write_byte(0xfffffe14, 0x00);
+        mem.write_byte(0xfffffe15, 0x02);
+        mem.write_byte(0xfffffe17, 0xf2);
+        mem.write_byte(0xfffffe14, 0x00);
+        mem.write_byte(0xfffffe15, 0x04);
+        mem.tick(8 * 4);
+        assert!(mem.fto(FrtPin::Ftoa));
+        assert!(!mem.fto(FrtPin::Ftob));
+        assert_eq!(mem.take_fto_changes(), vec![(FrtPin::Ftoa, true)]);
+        assert!(mem.take_fto_changes().is_empty());
+        mem.write_byte(0xfffffe17, 0xe1);
+        mem.regs.set16(FRCH, 0x0001);
+        mem.tick(8 * 3);
+        assert_eq!(mem.take_fto_changes(),
+                   vec![(FrtPin::Ftoa, false), (FrtPin::Ftob, true)]);
+    }
+
+    // INTC
+
+    #[test]
+    fn irl_vectors() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.set_irl(13, 0x99);
+        assert_eq!(mem.pending_irq(), Some((13, 70)));
+        mem.set_irl(1, 0x99);
+        assert_eq!(mem.pending_irq(), Some((1, 64)));
+        // external vector mode
+        mem.write_word(0xfffffee0, 0x0001);
+        assert_eq!(mem.pending_irq(), Some((1, 0x99)));
+        mem.set_irl(0, 0);
+        assert_eq!(mem.pending_irq(), None);
+    }
+
+    #[test]
+    fn intc_priority() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        // DIVU level 5, DMAC level 5, WDT level 7
+        mem.write_word(0xfffffee2, 0x5570);
+        mem.write_long(0xffffff0c, 0x21);
+        mem.write_long(0xffffffa0, 0x22);
+        mem.write_word(0xfffffee4, 0x2300);
+        mem.write_long(0xffffff08, 0x3);
+        mem.regs.set(CHCR0, 4, 0x6);
+        // same level, the DIVU goes first
+        assert_eq!(mem.pending_irq(), Some((5, 0x21)));
+        mem.write_long(0xffffff08, 0x2);
+        assert_eq!(mem.pending_irq(), Some((5, 0x22)));
+        // interval timer overflow at a higher level
+        mem.regs.set8(WTCSR, 0x98);
+        assert_eq!(mem.pending_irq(), Some((7, 0x23)));
+        // IRL wins a tie
+        mem.set_irl(7, 0);
+        assert_eq!(mem.pending_irq(), Some((7, 67)));
+        mem.set_irl(6, 0);
+        assert_eq!(mem.pending_irq(), Some((7, 0x23)));
+    }
+
+    #[test]
+    fn intc_sci_sources() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_word(0xfffffe60, 0x9000);
+        mem.write_word(0xfffffe62, 0x5051);
+        mem.write_word(0xfffffe64, 0x5253);
+        // TDRE and TEND are set after reset
+        assert_eq!(mem.pending_irq(), None);
+        mem.write_byte(0xfffffe02, 0x84);
+        assert_eq!(mem.pending_irq(), Some((9, 0x52)));
+        mem.write_byte(0xfffffe02, 0x04);
+        assert_eq!(mem.pending_irq(), Some((9, 0x53)));
+        mem.regs.set8(SSR, 0x44);
+        mem.write_byte(0xfffffe02, 0x40);
+        assert_eq!(mem.pending_irq(), Some((9, 0x51)));
+    }
+
+    // SCI
+
+    // a stream the test can look into while the SCI owns it
+    #[derive(Clone)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn sci_frame_cycles() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        // 8N1, φ/4, BRR 2
+        mem.write_byte(0xfffffe00, 0x01);
+        mem.write_byte(0xfffffe01, 2);
+        assert_eq!(mem.sci_frame_cycles(), 32 * 4 * 3 * 10);
+        // 7E2
+        mem.write_byte(0xfffffe00, 0x69);
+        assert_eq!(mem.sci_frame_cycles(), 32 * 4 * 3 * 11);
+        // clocked synchronous
+        mem.write_byte(0xfffffe00, 0x81);
+        assert_eq!(mem.sci_frame_cycles(), 4 * 4 * 3 * 8);
+    }
+
+    #[test]
+    fn sci_transmit() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        let out = Shared(Arc::new(Mutex::new(Vec::new())));
+        mem.connect_sci(io::empty(), out.clone());
+        // 8N1 at 320 cycles a frame
+        mem.write_byte(0xfffffe01, 0);
+        mem.write_byte(0xfffffe02, SCR_TE);
+        for &byte in b"hi" {
+            assert_eq!(mem.read_byte(0xfffffe04) & SSR_TDRE, SSR_TDRE);
+            mem.write_byte(0xfffffe03, byte);
+            mem.write_byte(0xfffffe04, 0x00);
+            assert_eq!(mem.read_byte(0xfffffe04), 0x00);
+            mem.tick(1);
+        }
+        assert_eq!(mem.read_byte(0xfffffe04), 0x00);
+        mem.tick(317);
+        assert_eq!(*out.0.lock().unwrap(), b"");
+        mem.tick(1);
+        assert_eq!(*out.0.lock().unwrap(), b"h");
+        assert_eq!(mem.read_byte(0xfffffe04), SSR_TDRE);
+        mem.tick(320);
+        assert_eq!(*out.0.lock().unwrap(), b"hi");
+        assert_eq!(mem.read_byte(0xfffffe04), SSR_TDRE | SSR_TEND);
+    }
+
+    // tick until the SCI has something for us, giving the thread reading
+    // the input the time it needs
+    fn sci_wait(mem: &mut Sh7604Mem<NoMem>, flags: u8) -> u8 {
+        for _ in 0..1000 {
+            mem.tick(320);
+            let ssr = mem.read_byte(0xfffffe04);
+            if ssr & flags != 0 {
+                return ssr;
+            }
+            thread::sleep(::std::time::Duration::from_millis(1));
+        }
+        panic!("nothing received");
+    }
+
+    #[test]
+    fn sci_receive() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.connect_sci(&b"abc"[..], io::sink());
+        mem.write_word(0xfffffe60, 0x9000);
+        mem.write_word(0xfffffe62, 0x5051);
+        mem.write_byte(0xfffffe01, 0);
+        mem.write_byte(0xfffffe02, SCR_RE | SCR_RIE);
+        sci_wait(&mut mem, SSR_RDRF);
+        assert_eq!(mem.read_byte(0xfffffe05), b'a');
+        assert_eq!(mem.pending_irq(), Some((9, 0x51)));
+        mem.write_byte(0xfffffe04, !(SSR_RDRF | SSR_MPBT));
+        sci_wait(&mut mem, SSR_RDRF);
+        assert_eq!(mem.read_byte(0xfffffe05), b'b');
+        // 'c' overruns
+        assert_eq!(sci_wait(&mut mem, SSR_ORER), 0xe4);
+        assert_eq!(mem.read_byte(0xfffffe05), b'b');
+        assert_eq!(mem.pending_irq(), Some((9, 0x50)));
+    }
+
+    // DIVU
+
+    #[test]
+    fn divu_32_32() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_long(0xffffff00, 7);
+        mem.write_long(0xffffff04, -100i32 as u32);
+        assert_eq!(mem.read_long(0xffffff04), -14i32 as u32);
+        assert_eq!(mem.read_long(0xffffff14), -14i32 as u32);
+        assert_eq!(mem.read_long(0xffffff10), -2i32 as u32);
+        // through the mirror
+        mem.write_long(0xffffff20, -7i32 as u32);
+        mem.write_long(0xffffff24, 100);
+        assert_eq!(mem.read_long(0xffffff3c), -14i32 as u32);
+        assert_eq!(mem.read_long(0xffffff38), 2);
+        assert_eq!(mem.read_long(0xffffff08), 0);
+    }
+
+    #[test]
+    fn divu_64_32() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_long(0xffffff00, 0x10000);
+        mem.write_long(0xffffff10, 0x1234);
+        mem.write_long(0xffffff14, 0x56789abc);
+        assert_eq!(mem.read_long(0xffffff14), 0x12345678);
+        assert_eq!(mem.read_long(0xffffff04), 0x12345678);
+        assert_eq!(mem.read_long(0xffffff10), 0x9abc);
+        mem.write_long(0xffffff10, 0xffffffff);
+        mem.write_long(0xffffff1c, 0xfffe0000);
+        assert_eq!(mem.read_long(0xffffff14), 0xfffffffe);
+        assert_eq!(mem.read_long(0xffffff10), 0);
+    }
+
+    #[test]
+    fn divu_overflow() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        // saturates by the sign of the result
+        mem.write_long(0xffffff00, 0);
+        mem.write_long(0xffffff04, 5);
+        assert_eq!(mem.read_long(0xffffff04), 0x7fffffff);
+        assert_eq!(mem.read_long(0xffffff08), DVCR_OVF);
+        mem.write_long(0xffffff00, 2);
+        mem.write_long(0xffffff10, 0xffffff00);
+        mem.write_long(0xffffff14, 0);
+        assert_eq!(mem.read_long(0xffffff14), 0x80000000);
+        assert_eq!(mem.pending_irq(), None);
+        // with OVFIE the dividend is left, and the interrupt is requested
+        mem.write_long(0xffffff08, DVCR_OVFIE);
+        mem.write_long(0xffffff0c, 0x50);
+        mem.write_word(0xfffffee2, 0x3000);
+        mem.write_long(0xffffff10, 1);
+        mem.write_long(0xffffff14, 0);
+        assert_eq!(mem.read_long(0xffffff10), 1);
+        assert_eq!(mem.read_long(0xffffff14), 0);
+        assert_eq!(mem.pending_irq(), Some((3, 0x50)));
+        mem.write_long(0xffffff08, DVCR_OVFIE);
+        assert_eq!(mem.pending_irq(), None);
+    }
+
+    #[test]
+    fn divu_latency() {
+        let mut mem = Sh7604Mem::new(NoMem);
+        mem.write_long(0xffffff00, 3);
+        mem.write_long(0xffffff04, 9);
+        assert_eq!(mem.access_cycles(0xffffff04, 4), 0);
+        mem.tick(1);
+        // looking at it from outside doesn't wait
+        mem.read_long(0xffffff04);
+        mem.tick(0);
+        assert_eq!(mem.take_stall(), 0);
+        assert_eq!(mem.access_cycles(0xffffff24, 4), 38);
+        assert_eq!(mem.access_cycles(0xffffff24, 4), 0);
+        // a new division waits for the one before
+        mem.write_long(0xffffff04, 9);
+        mem.tick(1);
+        mem.write_long(0xffffff04, 9);
+        assert_eq!(mem.access_cycles(0xffffff04, 4), 38);
+        // no wait once it's done
+        mem.tick(38 + 39);
+        assert_eq!(mem.access_cycles(0xffffff04, 4), 0);
+    }
+
+    // DMAC
+
+    // set up channel ch and enable the DMAC
+    fn dma_setup(mem: &mut Sh7604Mem<Ram>, ch: u32, sar: u32, dar: u32,
+                 tcr: u32, chcr: u32) {
+        let base = 0xffffff80 + ch * 0x10;
+        mem.write_long(base, sar);
+        mem.write_long(base + 0x4, dar);
+        mem.write_long(base + 0x8, tcr);
+        mem.write_long(base + 0xc, chcr);
+        mem.write_long(0xffffffb0, 0x1);
+        // no wait states, so an access takes 2 cycles
+        mem.write_long(0xffffffe8, 0xa55a0000);
+    }
+
+    #[test]
+    fn dma_auto_burst() {
+        let mut mem = Sh7604Mem::new(Ram::new());
+        for i in 0..8 {
+            mem.user.write_long(0x100 + i * 4, i);
+        }
+        // long units, both addresses incrementing
+        dma_setup(&mut mem, 0, 0x100, 0x200, 8, 0x5a15);
+        mem.tick(1);
+        for i in 0..8 {
+            assert_eq!(mem.read_long(0x200 + i * 4), i);
+        }
+        assert_eq!(mem.read_long(0xffffff88), 0);
+        assert_eq!(mem.read_long(0xffffff80), 0x120);
+        assert_eq!(mem.read_long(0xffffff8c) & CHCR_TE, CHCR_TE);
+        assert_eq!(mem.take_stall(), 32);
+        assert_eq!(mem.take_stall(), 0);
+    }
+
+    #[test]
+    fn dma_cycle_steal() {
+        let mut mem = Sh7604Mem::new(Ram::new());
+        mem.user.write_word(0x100, 0x1111);
+        mem.user.write_word(0x102, 0x2222);
+        // word units, source decrementing, destination fixed
+        dma_setup(&mut mem, 1, 0x102, 0x200, 2, 0x2605);
+        mem.tick(1);
+        assert_eq!(mem.read_word(0x200), 0x2222);
+        assert_eq!(mem.read_long(0xffffff98), 1);
+        assert_eq!(mem.take_stall(), 4);
+        mem.tick(1);
+        assert_eq!(mem.read_word(0x200), 0x1111);
+        assert_eq!(mem.read_long(0xffffff90), 0xfe);
+        // done, and the end of transfer interrupt is up
+        mem.write_word(0xfffffee2, 0x0b00);
+        mem.write_long(0xffffffa8, 0x48);
+        assert_eq!(mem.pending_irq(), Some((11, 0x48)));
+        // clearing TE takes it down
+        mem.write_long(0xffffff9c, 0x2605);
+        assert_eq!(mem.pending_irq(), None);
+    }
+
+    #[test]
+    fn dma_16_byte_units() {
+        let mut mem = Sh7604Mem::new(Ram::new());
+        for i in 0..8 {
+            mem.user.write_long(0x100 + i * 4, 0x10 + i);
+        }
+        dma_setup(&mut mem, 0, 0x100, 0x200, 8, 0x5e11);
+        mem.tick(1);
+        assert_eq!(mem.read_long(0x21c), 0x17);
+        assert_eq!(mem.read_long(0xffffff88), 0);
+        assert_eq!(mem.take_stall(), 32);
+    }
+
+    #[test]
+    fn dma_dreq_single_address() {
+        let mut mem = Sh7604Mem::new(Ram::new());
+        mem.user.write_byte(0x100, 0xaa);
+        mem.user.write_byte(0x101, 0xbb);
+        // byte units, memory to device, on the falling edge of DREQ
+        dma_setup(&mut mem, 0, 0x100, 0, 2, 0x1049);
+        mem.set_dreq(0, true);
+        mem.tick(1);
+        assert!(mem.user.dack_out.is_empty());
+        mem.set_dreq(0, false);
+        mem.tick(1);
+        mem.tick(1);
+        assert_eq!(mem.user.dack_out, vec![0xaa]);
+        mem.set_dreq(0, true);
+        mem.set_dreq(0, false);
+        mem.tick(1);
+        assert_eq!(mem.user.dack_out, vec![0xaa, 0xbb]);
+        assert_eq!(mem.take_stall(), 4);
+
+        // device to memory while DREQ is low
+        dma_setup(&mut mem, 0, 0, 0x300, 3, 0x4109);
+        mem.tick(1);
+        mem.tick(1);
+        assert_eq!(mem.read_byte(0x300), 1);
+        assert_eq!(mem.read_byte(0x301), 2);
+        mem.set_dreq(0, true);
+        mem.tick(1);
+        assert_eq!(mem.read_byte(0x302), 0);
+    }
+
+    #[test]
+    fn dma_priority() {
+        // channel 0 transfers alone, then both channels want the bus
+        let run = |dmaor| {
+            let mut mem = Sh7604Mem::new(Ram::new());
+            mem.user.write_byte(0x100, 1);
+            mem.user.write_byte(0x101, 3);
+            mem.user.write_byte(0x180, 2);
+            dma_setup(&mut mem, 0, 0x100, 0x200, 2, 0x1201);
+            mem.write_long(0xffffffb0, dmaor);
+            mem.tick(1);
+            dma_setup(&mut mem, 1, 0x180, 0x200, 1, 0x1201);
+            mem.write_long(0xffffffb0, dmaor);
+            mem.tick(1);
+            mem.read_byte(0x200)
+        };
+        // with fixed priority channel 0 always goes first, with round robin
+        // the channel that went last goes last
+        assert_eq!(run(0x1), 2);
+        assert_eq!(run(0x9), 3);
+    }
+
+    #[test]
+    fn dma_stops() {
+        let mut mem = Sh7604Mem::new(Ram::new());
+        // nops, and an address error handler at 0x400
+        mem.user.write_long(9 * 4, 0x400);
+        mem.user.write_word(0x400, 0x0009);
+        mem.user.write_word(0x1000, 0x0009);
+        let mut cpu = Sh2::new();
+        cpu.reset(0x1000, 0x8000);
+        // misaligned long transfer
+        dma_setup(&mut mem, 0, 0x102, 0x200, 1, 0x1a01);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(mem.read_long(0xffffffb0), 0x5);
+        assert_eq!(mem.read_long(0xffffff88), 1);
+        // the cpu takes an address error exception for it
+        cpu.step(&mut mem).unwrap();
+        let regs = cpu.get_regs();
+        assert_eq!((regs.pc, regs.gpr[15]), (0x402, 0x8000 - 8));
+        assert_eq!(mem.user.read_long(0x8000 - 8), 0x1002);
+        // 16 byte units have to be aligned to 16 bytes
+        dma_setup(&mut mem, 0, 0x104, 0x200, 4, 0x5e11);
+        mem.tick(1);
+        assert_eq!(mem.read_long(0xffffffb0), 0x5);
+        assert_eq!(mem.read_long(0xffffff88), 4);
+        assert!(mem.take_address_error());
+        // an NMI stops the DMAC until NMIF is cleared
+        dma_setup(&mut mem, 0, 0x100, 0x200, 2, 0x1a11);
+        mem.set_nmi(false);
+        mem.tick(1);
+        assert_eq!(mem.read_long(0xffffff88), 2);
+        assert_eq!(mem.read_long(0xffffffb0), 0x3);
+        mem.write_long(0xffffffb0, 0x1);
+        mem.tick(1);
+        assert_eq!(mem.read_long(0xffffff88), 0);
+    }
 }